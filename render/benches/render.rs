@@ -15,9 +15,9 @@ fn bench_render_page(c: &mut Criterion) {
     group.sample_size(50);
     group.warm_up_time(Duration::from_secs(1));
 
-    let mut cache = Cache::new();
+    let cache = Cache::new().unwrap();
     let mut secen = Scene::new();
-    let mut backend = SceneBackend::new(cache, &mut secen);
+    let mut backend = SceneBackend::new(&cache, &mut secen);
     for (i, page) in file.pages().enumerate() {
         if let Ok(page) = page {
             group.bench_function(&format!("page {}", i), |b| b.iter(|| render_page(&mut backend, &resolver, &page, Default::default()).unwrap()));
@@ -10,10 +10,10 @@ fn render_file(path: &Path) -> Vec<Scene> {
     let file = FileOptions::cached().open(path).unwrap();
     let resolver = file.resolver();
     
-    let mut cache = Cache::new();
+    let cache = Cache::new().unwrap();
     // file.pages().map(|page| {
     //     let p: &Page = &*page.unwrap();
-    //     let mut backend = SceneBackend::new(&mut cache);
+    //     let mut backend = SceneBackend::new(&cache, &mut scene);
     //     render_page(&mut backend, &resolver, p, Default::default()).unwrap();
     //     backend.finish()
     // }).collect()
@@ -0,0 +1,43 @@
+//! With the `embed-fonts` feature, collects the bundled fonts from `bundled-fonts/` in `$OUT_DIR/fonts`.
+//!
+//! The build never downloads anything. The fonts have to be placed in `bundled-fonts/` beforehand,
+//! see `bundled-fonts/README.md`.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const FONTS: &[&str] = &[
+    "NimbusSans-Regular.otf", "NimbusSans-Bold.otf", "NimbusSans-Italic.otf", "NimbusSans-BoldItalic.otf",
+    "NimbusRoman-Regular.otf", "NimbusRoman-Bold.otf", "NimbusRoman-Italic.otf", "NimbusRoman-BoldItalic.otf",
+    "NimbusMonoPS-Regular.otf", "NimbusMonoPS-Bold.otf", "NimbusMonoPS-Italic.otf", "NimbusMonoPS-BoldItalic.otf",
+    "StandardSymbolsPS.otf", "D050000L.otf",
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=bundled-fonts");
+    println!("cargo:rerun-if-env-changed=DOCS_RS");
+    if env::var_os("CARGO_FEATURE_EMBED_FONTS").is_none() {
+        return;
+    }
+    let src = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("bundled-fonts");
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("fonts");
+    fs::create_dir_all(&out).unwrap();
+
+    // docs.rs has no fonts and does not need them
+    let docs = env::var_os("DOCS_RS").is_some();
+    let mut missing = vec![];
+    for &file in FONTS {
+        let local = src.join(file);
+        if local.is_file() {
+            fs::copy(&local, out.join(file)).unwrap();
+        } else if docs {
+            fs::write(out.join(file), b"").unwrap();
+        } else {
+            missing.push(file);
+        }
+    }
+    if !missing.is_empty() {
+        panic!("the embed-fonts feature needs {} in {:?}. run bundled-fonts/fetch.sh to download and verify them.", missing.join(", "), src);
+    }
+}
@@ -0,0 +1,23 @@
+#!/bin/sh
+# Download the bundled fonts from a pinned release next to this script and verify them against SHA256SUMS.
+# `./fetch.sh --record` writes SHA256SUMS from the downloaded files instead; review the files before committing it.
+set -eu
+tag=20200910
+base=https://raw.githubusercontent.com/ArtifexSoftware/urw-base35-fonts/$tag/fonts
+cd "$(dirname "$0")"
+for style in Regular Bold Italic BoldItalic; do
+    for family in NimbusSans NimbusRoman NimbusMonoPS; do
+        curl -fsSLO "$base/$family-$style.otf"
+    done
+done
+curl -fsSLO "$base/StandardSymbolsPS.otf"
+curl -fsSLO "$base/D050000L.otf"
+if [ "${1:-}" = "--record" ]; then
+    sha256sum *.otf > SHA256SUMS
+elif [ -f SHA256SUMS ]; then
+    sha256sum -c SHA256SUMS
+else
+    echo "SHA256SUMS is missing, the downloaded fonts are not verified" >&2
+    rm -f *.otf
+    exit 1
+fi
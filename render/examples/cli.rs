@@ -0,0 +1,55 @@
+// the tests call the subcommands directly
+#![cfg_attr(test, allow(dead_code))]
+
+use pdf::file::FileOptions;
+use pdf_render::tracer::Tracer;
+use pdf_render::{Cache, FontReport};
+use pdf_render::render_page;
+
+const USAGE: &str = "usage: cli fonts <file.pdf>";
+
+fn fonts(path: &str) {
+    let cache = Cache::new().unwrap();
+    println!("{:#}", font_report(path, &cache).to_json());
+}
+
+fn font_report(path: &str, cache: &Cache) -> FontReport {
+    let file = FileOptions::cached().open(path).unwrap();
+    let resolver = file.resolver();
+
+    for page in file.pages() {
+        let p = page.unwrap();
+        let mut backend = Tracer::new(cache);
+        if let Err(e) = render_page(&mut backend, &resolver, &p, Default::default()) {
+            eprintln!("{}", e);
+        }
+    }
+    cache.font_report()
+}
+
+fn main() {
+    env_logger::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(|s| s.as_str()).collect::<Vec<_>>()[..] {
+        ["fonts", path] => fonts(path),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fonts_subcommand() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/compressed.tracemonkey-pldi-09.pdf");
+        let report = font_report(path, &Cache::builder().build().unwrap()).to_json();
+        let fonts = report["fonts"].as_array().unwrap();
+        assert!(!fonts.is_empty());
+        assert!(fonts.iter().all(|f| f["embedded"] == true && f["name"].is_string()));
+        assert_eq!(report["missing"], serde_json::json!([]));
+    }
+}
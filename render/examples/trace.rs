@@ -1,5 +1,6 @@
 use pdf::file::{FileOptions};
-use pdf_render::tracer::{TraceCache, Tracer};
+use pdf_render::tracer::Tracer;
+use pdf_render::Cache;
 use pdf_render::render_page;
 
 fn main() {
@@ -9,10 +10,10 @@ fn main() {
     let file = FileOptions::cached().open(&arg).unwrap();
     let resolver = file.resolver();
     
-    let mut cache = TraceCache::new();
+    let cache = Cache::new().unwrap();
     for page in file.pages() {
         let p = page.unwrap();
-        let mut backend = Tracer::new(&mut cache);
+        let mut backend = Tracer::new(&cache);
         render_page(&mut backend, &resolver, &p, Default::default()).unwrap();
         let items = backend.finish();
         for i in items {
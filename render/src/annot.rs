@@ -0,0 +1,144 @@
+use pdf::object::*;
+use pdf::error::Result;
+use pdf::content::FormXObject;
+
+use pathfinder_geometry::{
+    vector::Vector2F,
+    rect::RectF, transform2d::Transform2F,
+};
+use crate::backend::Backend;
+use crate::renderstate::RenderState;
+use crate::appearance::synthesize_appearance;
+use crate::options::{RenderOptions, RenderContext};
+use crate::page_box_rect;
+
+/// Whether the page is rendered for display or for printing.
+///
+/// This decides which annotations are visible.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum RenderIntent {
+    #[default]
+    Screen,
+    Print,
+}
+
+// annotation flags (PDF 32000-1:2008, 12.5.3)
+const FLAG_HIDDEN: u32 = 1 << 1;
+const FLAG_PRINT: u32 = 1 << 2;
+const FLAG_NO_VIEW: u32 = 1 << 5;
+
+pub fn annotation_visible(annot: &Annot, intent: RenderIntent) -> bool {
+    let flags = annot.annot_flags;
+    if flags & FLAG_HIDDEN != 0 {
+        return false;
+    }
+    match intent {
+        RenderIntent::Screen => flags & FLAG_NO_VIEW == 0,
+        RenderIntent::Print => flags & FLAG_PRINT != 0,
+    }
+}
+
+/// Draw the normal appearance streams of all visible annotations on the page.
+///
+/// Annotations without appearance streams get one generated from their geometry, if the type is supported.
+///
+/// `root_transformation` is the transform returned by `render_page`.
+/// Uses the page box, intent, optional content and content toggles from `options`.
+pub fn render_annotations(backend: &mut impl Backend, resolve: &impl Resolve, page: &Page, root_transformation: Transform2F, options: &RenderOptions) -> Result<()> {
+    draw_annotations(backend, resolve, page, root_transformation, &RenderContext::new(options))
+}
+
+pub(crate) fn draw_annotations(backend: &mut impl Backend, resolve: &impl Resolve, page: &Page, root_transformation: Transform2F, context: &RenderContext) -> Result<()> {
+    let RenderOptions { intent, optional_content, page_box, .. } = *context.options;
+    let resources = t!(page.resources());
+    let clip = page_box_rect(page, page_box)?;
+    let annotations = t!(page.annotations.load(resolve));
+    for annot in annotations.iter() {
+        if annot.subtype.as_str() == "Popup" || !annotation_visible(annot, intent) {
+            continue;
+        }
+        if let (Some(config), Some(oc)) = (optional_content, annot.other.get("OC")) {
+            if !config.is_visible(oc, resolve) {
+                continue;
+            }
+        }
+        if let Err(e) = draw_annotation(backend, resolve, &resources, annot, root_transformation, clip, context) {
+            if !context.tolerate() {
+                return Err(e);
+            }
+            warn!("skipping annotation {:?}: {}", annot.subtype, e);
+            backend.set_clip_path(None);
+        }
+    }
+    Ok(())
+}
+
+fn draw_annotation(backend: &mut impl Backend, resolve: &impl Resolve, resources: &Resources, annot: &Annot, root_transformation: Transform2F, clip: RectF, context: &RenderContext) -> Result<()> {
+    let rect = match annot.rect {
+        Some(rect) => rect,
+        None => return Ok(())
+    };
+    let streams = match annot.appearance_streams {
+        Some(ref streams) => streams,
+        None => {
+            if !synthesize_appearance(backend, resolve, resources, annot, root_transformation, clip, context)? {
+                debug!("no appearance for annotation {:?}", annot.subtype);
+            }
+            return Ok(());
+        }
+    };
+    let entry = t!(resolve.get(streams.normal));
+    let form = match *entry {
+        AppearanceStreamEntry::Single(ref form) => form,
+        AppearanceStreamEntry::Dict(ref states) => {
+            match annot.appearance_state.as_ref().and_then(|state| states.get(state)) {
+                Some(AppearanceStreamEntry::Single(ref form)) => form,
+                _ => return Ok(())
+            }
+        }
+    };
+    debug!("annotation {:?} at {:?}", annot.subtype, rect);
+    draw_appearance(backend, resolve, resources, form, rect, root_transformation, clip, context)
+}
+
+/// Place the form into `rect` using the algorithm from PDF 32000-1:2008, 12.5.5.
+fn draw_appearance(backend: &mut impl Backend, resolve: &impl Resolve, page_resources: &Resources, form: &FormXObject, rect: Rect, root_transformation: Transform2F, clip: RectF, context: &RenderContext) -> Result<()> {
+    let dict = form.dict();
+    let matrix = dict.matrix.map(|m| Transform2F::row_major(m.a, m.c, m.e, m.b, m.d, m.f)).unwrap_or_default();
+    let bbox = RectF::from_points(
+        Vector2F::new(dict.bbox.left, dict.bbox.bottom),
+        Vector2F::new(dict.bbox.right, dict.bbox.top)
+    );
+    let target = RectF::from_points(
+        Vector2F::new(rect.left.min(rect.right), rect.bottom.min(rect.top)),
+        Vector2F::new(rect.left.max(rect.right), rect.bottom.max(rect.top))
+    );
+    let transformed = matrix * bbox;
+    if transformed.width() == 0.0 || transformed.height() == 0.0 {
+        return Ok(());
+    }
+    let a = Transform2F::from_translation(target.origin())
+        * Transform2F::from_scale(target.size() / transformed.size())
+        * Transform2F::from_translation(-transformed.origin());
+
+    let resources = match dict.resources {
+        Some(ref r) => &**r,
+        None => page_resources
+    };
+    let ops = t!(form.operations(resolve));
+
+    backend.set_clip_path(None);
+    let mut renderstate = RenderState::new(backend, resolve, resources, root_transformation, context);
+    renderstate.clip_rect(clip);
+    renderstate.concat_transform(a * matrix);
+    renderstate.clip_rect(bbox);
+    let result = ops.iter().enumerate().try_for_each(|(i, op)| {
+        debug!("annot op {}: {:?}", i, op);
+        renderstate.draw_op(op, i)
+    });
+    renderstate.close_marked_content();
+    drop(renderstate);
+    backend.set_clip_path(None);
+
+    result
+}
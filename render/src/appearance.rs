@@ -0,0 +1,324 @@
+//! Appearances for annotations that come without an `/AP` entry.
+
+use pdf::object::*;
+use pdf::primitive::{Primitive, PdfString};
+use pdf::content::{Op, Color, Rgb, Point};
+use pdf::error::Result;
+
+use pathfinder_geometry::{
+    vector::Vector2F,
+    rect::RectF, transform2d::Transform2F,
+};
+use pathfinder_content::{
+    fill::FillRule,
+    stroke::{LineCap, LineJoin, StrokeStyle},
+    outline::{Outline, Contour},
+};
+use crate::backend::{Backend, Stroke, DrawMode};
+use crate::renderstate::RenderState;
+use crate::options::RenderContext;
+use crate::Fill;
+
+/// highlights are drawn translucent so the marked text stays readable
+const HIGHLIGHT_ALPHA: f32 = 0.4;
+
+// control point distance for approximating a quarter circle with a cubic bezier
+const KAPPA: f32 = 0.552_284_8;
+
+/// Draw an appearance for `annot` built from its geometry and style entries.
+///
+/// Returns `false` if the annotation type is not supported.
+pub fn synthesize_appearance(backend: &mut impl Backend, resolve: &impl Resolve, resources: &Resources, annot: &Annot, root_transformation: Transform2F, clip: RectF, context: &RenderContext) -> Result<bool> {
+    let color = annot.color.as_ref().and_then(|c| parse_color(c, resolve));
+    let interior = annot.other.get("IC").and_then(|c| parse_color(c, resolve));
+    let opacity = number(annot.other.get("CA"), resolve).unwrap_or(1.0);
+    let stroke = border(annot, resolve);
+    let rect = annot.rect.map(|r| RectF::from_points(
+        Vector2F::new(r.left.min(r.right), r.bottom.min(r.top)),
+        Vector2F::new(r.left.max(r.right), r.bottom.max(r.top))
+    ));
+
+    // paths in page space, drawn like the content stream
+    let mut paths: Vec<(Outline, DrawMode)> = vec![];
+    let mut draw = |outline: &Outline, mode: DrawMode| paths.push((outline.clone(), mode));
+
+    match annot.subtype.as_str() {
+        "Highlight" => {
+            let fill = color.unwrap_or(Fill::Solid(1.0, 1.0, 0.0));
+            let mut outline = Outline::new();
+            for [p1, p2, p3, p4] in quads(annot, resolve) {
+                let mut contour = Contour::new();
+                contour.push_endpoint(p3);
+                contour.push_endpoint(p4);
+                contour.push_endpoint(p2);
+                contour.push_endpoint(p1);
+                contour.close();
+                outline.push_contour(contour);
+            }
+            draw(&outline, DrawMode::Fill(fill, opacity * HIGHLIGHT_ALPHA));
+        }
+        "Underline" | "StrikeOut" | "Squiggly" => {
+            let fill = color.unwrap_or(Fill::black());
+            let strike = annot.subtype.as_str() == "StrikeOut";
+            let squiggly = annot.subtype.as_str() == "Squiggly";
+            for [p1, p2, p3, p4] in quads(annot, resolve) {
+                let height = (p1 - p3).length();
+                let t = if strike { 0.5 } else { 1.0 / 7.0 };
+                let (start, end) = (p3 + (p1 - p3) * t, p4 + (p2 - p4) * t);
+                let mut contour = Contour::new();
+                contour.push_endpoint(start);
+                if squiggly && height > 0.0 {
+                    // zigzag with a period of a quarter of the line height
+                    let up = (p1 - p3) * (1.0 / 8.0);
+                    let n = ((end - start).length() / (height * 0.125)).ceil().max(1.0) as usize;
+                    for i in 1 ..= n {
+                        let p = start + (end - start) * (i as f32 / n as f32);
+                        contour.push_endpoint(if i % 2 == 1 { p + up } else { p });
+                    }
+                } else {
+                    contour.push_endpoint(end);
+                }
+                let mut outline = Outline::new();
+                outline.push_contour(contour);
+                draw(&outline, DrawMode::Stroke(fill, opacity, Stroke {
+                    dash_pattern: None,
+                    style: StrokeStyle { line_width: height / 14.0, line_cap: LineCap::Butt, line_join: LineJoin::Miter(10.0) }
+                }));
+            }
+        }
+        "Square" | "Circle" => {
+            let width = stroke.style.line_width;
+            let rect = match rect {
+                Some(r) => r.contract(width * 0.5),
+                None => return Ok(true)
+            };
+            let contour = if annot.subtype.as_str() == "Square" {
+                Contour::from_rect(rect)
+            } else {
+                ellipse(rect)
+            };
+            let mut outline = Outline::new();
+            outline.push_contour(contour);
+            // no border without width
+            let mode = match (interior, color) {
+                (Some(i), Some(c)) if width > 0.0 => DrawMode::FillStroke(i, opacity, c, opacity, stroke),
+                (Some(i), _) => DrawMode::Fill(i, opacity),
+                (None, Some(c)) if width > 0.0 => DrawMode::Stroke(c, opacity, stroke),
+                _ => return Ok(true),
+            };
+            draw(&outline, mode);
+        }
+        "Ink" => {
+            let fill = color.unwrap_or(Fill::black());
+            let mut outline = Outline::new();
+            if let Some(Ok(paths)) = annot.ink_list.as_ref().map(|p| p.clone().resolve(resolve).and_then(|p| p.into_array())) {
+                for path in paths {
+                    let points = points(&path, resolve);
+                    if let Some((&first, rest)) = points.split_first() {
+                        let mut contour = Contour::new();
+                        contour.push_endpoint(first);
+                        for &p in rest {
+                            contour.push_endpoint(p);
+                        }
+                        outline.push_contour(contour);
+                    }
+                }
+            }
+            let style = StrokeStyle { line_cap: LineCap::Round, line_join: LineJoin::Round, .. stroke.style };
+            draw(&outline, DrawMode::Stroke(fill, opacity, Stroke { style, .. stroke }));
+        }
+        "Line" => {
+            let fill = color.unwrap_or(Fill::black());
+            let points = annot.other.get("L").map(|l| points(l, resolve)).unwrap_or_default();
+            if let [a, b] = points[..] {
+                let mut contour = Contour::new();
+                contour.push_endpoint(a);
+                contour.push_endpoint(b);
+                let mut outline = Outline::new();
+                outline.push_contour(contour);
+                draw(&outline, DrawMode::Stroke(fill, opacity, stroke));
+            }
+        }
+        "FreeText" => {
+            let rect = match rect {
+                Some(r) => r,
+                None => return Ok(true)
+            };
+            let outline = Outline::from_rect(rect.contract(stroke.style.line_width * 0.5));
+            if let Some(background) = color {
+                draw(&outline, DrawMode::Fill(background, opacity));
+            }
+            if stroke.style.line_width > 0.0 {
+                draw(&outline, DrawMode::Stroke(Fill::black(), opacity, stroke));
+            }
+        }
+        _ => return Ok(false)
+    }
+
+    let mut renderstate = RenderState::new(backend, resolve, resources, root_transformation, context);
+    renderstate.clip_rect(clip);
+    for (outline, mode) in paths {
+        renderstate.draw_outline(outline, &mode, FillRule::Winding);
+    }
+    drop(renderstate);
+    backend.set_clip_path(None);
+    if let (Some(rect), "FreeText") = (rect, annot.subtype.as_str()) {
+        free_text(backend, resolve, resources, annot, rect, root_transformation, clip, context)?;
+    }
+    Ok(true)
+}
+
+/// Lay out `/Contents` line by line using the font and color from `/DA`.
+///
+/// The font is looked up in the form resources of the options (AcroForm `/DR`), then in the page resources.
+fn free_text(backend: &mut impl Backend, resolve: &impl Resolve, resources: &Resources, annot: &Annot, rect: RectF, root_transformation: Transform2F, clip: RectF, context: &RenderContext) -> Result<()> {
+    let text = match annot.contents {
+        Some(ref s) => s.to_string_lossy(),
+        None => return Ok(())
+    };
+    let da = match annot.other.get("DA").map(|p| p.clone().resolve(resolve).and_then(|p| p.into_string())) {
+        Some(Ok(da)) => da.to_string_lossy(),
+        _ => return Ok(())
+    };
+    let (font, size, color) = parse_da(&da);
+    let form_resources = context.options.form_resources.filter(|dr| font.as_ref().map_or(false, |name| dr.fonts.contains_key(name.as_str())));
+    let (font, resources) = match (font, form_resources) {
+        (Some(name), Some(dr)) => (name, dr),
+        (Some(name), None) if resources.fonts.contains_key(name.as_str()) => (name, resources),
+        (other, _) => {
+            debug!("FreeText font {:?} not in the form or page resources", other);
+            return Ok(());
+        }
+    };
+    let size = if size > 0.0 { size } else { 12.0 };
+
+    let mut ops = vec![
+        Op::BeginText,
+        Op::TextFont { name: font.as_str().into(), size },
+        Op::FillColor { color },
+        Op::Leading { leading: size * 1.2 },
+        Op::MoveTextPosition { translation: Point { x: rect.min_x() + 2.0, y: rect.max_y() - 2.0 - size } },
+    ];
+    for (i, line) in text.lines().enumerate() {
+        if i > 0 {
+            ops.push(Op::TextNewline);
+        }
+        let data: Vec<u8> = line.chars().map(|c| if (c as u32) < 256 { c as u8 } else { b'?' }).collect();
+        ops.push(Op::TextDraw { text: PdfString::new(data.into()) });
+    }
+    ops.push(Op::EndText);
+
+    let mut renderstate = RenderState::new(backend, resolve, resources, root_transformation, context);
+    renderstate.clip_rect(clip);
+    renderstate.clip_rect(rect);
+    for (i, op) in ops.iter().enumerate() {
+        renderstate.draw_op(op, i)?;
+    }
+    drop(renderstate);
+    backend.set_clip_path(None);
+    Ok(())
+}
+
+/// extract font name, size and fill color from a default appearance string like `/Helv 12 Tf 0 0 1 rg`
+fn parse_da(da: &str) -> (Option<String>, f32, Color) {
+    let mut font = None;
+    let mut size = 0.0;
+    let mut color = Color::Gray(0.0);
+    let mut operands: Vec<&str> = vec![];
+    for token in da.split_whitespace() {
+        let num = |i: usize| operands.get(operands.len().wrapping_sub(i)).and_then(|s| s.parse::<f32>().ok()).unwrap_or(0.0);
+        match token {
+            "Tf" => {
+                size = num(1);
+                font = operands.iter().rev().nth(1).and_then(|s| s.strip_prefix('/')).map(String::from);
+            }
+            "g" => color = Color::Gray(num(1)),
+            "rg" => color = Color::Rgb(Rgb { red: num(3), green: num(2), blue: num(1) }),
+            "k" => {
+                let (c, m, y, k) = (num(4), num(3), num(2), num(1));
+                color = Color::Rgb(Rgb { red: 1.0 - (c + k).min(1.0), green: 1.0 - (m + k).min(1.0), blue: 1.0 - (y + k).min(1.0) });
+            }
+            _ => {
+                operands.push(token);
+                continue;
+            }
+        }
+        operands.clear();
+    }
+    (font, size, color)
+}
+
+fn ellipse(rect: RectF) -> Contour {
+    let c = rect.center();
+    let (rx, ry) = (rect.width() * 0.5, rect.height() * 0.5);
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    let p = |x: f32, y: f32| c + Vector2F::new(x, y);
+
+    let mut contour = Contour::new();
+    contour.push_endpoint(p(rx, 0.));
+    contour.push_cubic(p(rx, ky), p(kx, ry), p(0., ry));
+    contour.push_cubic(p(-kx, ry), p(-rx, ky), p(-rx, 0.));
+    contour.push_cubic(p(-rx, -ky), p(-kx, -ry), p(0., -ry));
+    contour.push_cubic(p(kx, -ry), p(rx, -ky), p(rx, 0.));
+    contour.close();
+    contour
+}
+
+/// border width and dash pattern from `/BS` or the older `/Border` array
+fn border(annot: &Annot, resolve: &impl Resolve) -> Stroke {
+    let mut width = 1.0;
+    let mut dash_pattern = None;
+    let bs = annot.other.get("BS").and_then(|p| p.clone().resolve(resolve).ok()).and_then(|p| p.into_dictionary().ok());
+    if let Some(bs) = bs {
+        width = number(bs.get("W"), resolve).unwrap_or(1.0);
+        let dashed = matches!(bs.get("S"), Some(Primitive::Name(ref s)) if s.as_str() == "D");
+        if dashed {
+            let pattern = bs.get("D").map(|d| numbers(d, resolve)).unwrap_or_else(|| vec![3.0]);
+            dash_pattern = Some((pattern, 0.0));
+        }
+    } else if let Some(border) = annot.border.as_ref() {
+        let values = numbers(border, resolve);
+        if let Some(&w) = values.get(2) {
+            width = w;
+        }
+        if let Ok(arr) = border.clone().resolve(resolve).and_then(|p| p.into_array()) {
+            if let Some(dash) = arr.get(3) {
+                dash_pattern = Some((numbers(dash, resolve), 0.0));
+            }
+        }
+    }
+    Stroke {
+        dash_pattern,
+        style: StrokeStyle { line_width: width, line_cap: LineCap::Butt, line_join: LineJoin::Miter(10.0) }
+    }
+}
+
+fn parse_color(p: &Primitive, resolve: &impl Resolve) -> Option<Fill> {
+    match numbers(p, resolve)[..] {
+        [g] => Some(Fill::Solid(g, g, g)),
+        [r, g, b] => Some(Fill::Solid(r, g, b)),
+        [c, m, y, k] => Some(Fill::Solid(1.0 - (c + k).min(1.0), 1.0 - (m + k).min(1.0), 1.0 - (y + k).min(1.0))),
+        _ => None
+    }
+}
+
+/// the four corners of each quadrilateral in `/QuadPoints`
+fn quads(annot: &Annot, resolve: &impl Resolve) -> Vec<[Vector2F; 4]> {
+    let points = annot.other.get("QuadPoints").map(|p| points(p, resolve)).unwrap_or_default();
+    points.chunks_exact(4).map(|c| [c[0], c[1], c[2], c[3]]).collect()
+}
+
+fn points(p: &Primitive, resolve: &impl Resolve) -> Vec<Vector2F> {
+    numbers(p, resolve).chunks_exact(2).map(|c| Vector2F::new(c[0], c[1])).collect()
+}
+
+fn numbers(p: &Primitive, resolve: &impl Resolve) -> Vec<f32> {
+    match p.clone().resolve(resolve).and_then(|p| p.into_array()) {
+        Ok(arr) => arr.iter().filter_map(|n| number(Some(n), resolve)).collect(),
+        Err(_) => vec![]
+    }
+}
+
+fn number(p: Option<&Primitive>, resolve: &impl Resolve) -> Option<f32> {
+    p?.clone().resolve(resolve).ok()?.as_number().ok()
+}
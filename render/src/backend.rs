@@ -7,7 +7,9 @@ use pathfinder_content::{
     stroke::{StrokeStyle},
     outline::Outline,
 };
+use pathfinder_color::ColorU;
 use pdf::{object::{Ref, XObject, ImageXObject, Resolve, Resources, MaybeRef}, content::Op};
+use pdf::primitive::{Name, Dictionary};
 use pdf::error::PdfError;
 use font::Glyph;
 use super::{FontEntry, TextSpan, Fill};
@@ -20,23 +22,49 @@ pub enum BlendMode {
     Darken
 }
 
+/// How image pixels are sampled when drawn at a different size.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Sampling {
+    /// keep the pixels sharp
+    Nearest,
+    /// interpolate between pixels
+    Smooth,
+}
+
 pub trait Backend {
     fn set_clip_path(&mut self, path: Option<&Outline>);
     fn draw(&mut self, outline: &Outline, mode: &DrawMode, fill_rule: FillRule, transform: Transform2F);
     fn set_view_box(&mut self, r: RectF);
+    /// Paint the page background over the view box `r`, before the page content.
+    /// Backends that only collect the content can leave it out.
+    fn fill_background(&mut self, r: RectF, color: ColorU) {
+        let fill = Fill::Solid(color.r as f32 / 255., color.g as f32 / 255., color.b as f32 / 255.);
+        self.draw(&Outline::from_rect(r), &DrawMode::Fill(fill, color.a as f32 / 255.), FillRule::Winding, Transform2F::default());
+    }
     fn draw_image(&mut self, xref: Ref<XObject>, im: &ImageXObject, resources: &Resources, transform: Transform2F, mode: BlendMode, resolve: &impl Resolve);
     fn draw_inline_image(&mut self, im: &Arc<ImageXObject>, resources: &Resources, transform: Transform2F, mode: BlendMode, resolve: &impl Resolve);
+    /// How the next image is sampled. Called before every `draw_image` and `draw_inline_image`.
+    fn set_image_sampling(&mut self, sampling: Sampling) {}
     fn draw_glyph(&mut self, glyph: &Glyph, mode: &DrawMode, transform: Transform2F) {
         self.draw(&glyph.path, mode, FillRule::Winding, transform);
     }
     fn get_font(&mut self, font_ref: &MaybeRef<PdfFont>, resolve: &impl Resolve) -> Result<Option<Arc<FontEntry>>, PdfError>;
     fn add_text(&mut self, span: TextSpan);
 
+    /// Marked content (`BMC`/`BDC` … `EMC`). `properties` is the resolved properties dictionary, if any.
+    /// Unmatched `EMC` operators are not reported.
+    fn begin_marked_content(&mut self, tag: &Name, properties: Option<&Dictionary>) {}
+    fn end_marked_content(&mut self) {}
+    /// Marked content point (`MP`/`DP`).
+    fn marked_content_point(&mut self, tag: &Name, properties: Option<&Dictionary>) {}
+
     /// The following functions are for debugging PDF files and not relevant for rendering them.
     fn bug_text_no_font(&mut self, data: &[u8]) {}
     fn bug_text_invisible(&mut self, text: &str) {}
     fn bug_postscript(&mut self, data: &[u8]) {}
     fn bug_op(&mut self, op_nr: usize) {}
+    /// `op_nr` failed with `error` and was skipped in lenient mode.
+    fn bug_op_failed(&mut self, op_nr: usize, op: &Op, error: &PdfError) {}
     fn inspect_op(&mut self, op: &Op) {}
 }
 #[derive(Clone)]
@@ -0,0 +1,162 @@
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use pathfinder_geometry::transform2d::Transform2F;
+use pdf::object::{Page, Resolve};
+use crate::{Cache, RasterBackend, RenderOptions, RenderError, ImageData, render_page_with};
+use crate::options::CancelToken;
+
+/// How `render_parallel` distributes the pages.
+#[derive(Clone, Default)]
+pub struct ParallelOptions<'a> {
+    /// number of threads, `None` for one per core
+    pub threads: Option<usize>,
+    /// pages that have not started when this is cancelled are skipped
+    pub cancel: CancelToken,
+    /// called with the number of finished pages and the total after each page, from the rendering thread
+    pub progress: Option<&'a (dyn Fn(usize, usize) + Sync)>,
+}
+
+/// Call `render` for every page on a pool of scoped threads.
+///
+/// The results are in the order of `pages`. Pages skipped because of cancellation are `None`.
+/// Share a `Cache` between the calls to load every font and image XObject only once per resolution level.
+pub fn render_parallel<P, T, E>(
+    pages: &[P],
+    options: &ParallelOptions,
+    render: impl Fn(usize, &P) -> Result<T, E> + Sync
+) -> Vec<Option<Result<T, E>>>
+    where P: Sync, T: Send, E: Send
+{
+    let threads = options.threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1))
+        .clamp(1, pages.len().max(1));
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+
+    let worker = || {
+        let mut results = vec![];
+        loop {
+            if options.cancel.is_cancelled() {
+                break;
+            }
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let page = match pages.get(i) {
+                Some(page) => page,
+                None => break
+            };
+            results.push((i, render(i, page)));
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            if let Some(progress) = options.progress {
+                progress(done, pages.len());
+            }
+        }
+        results
+    };
+
+    let mut out: Vec<Option<Result<T, E>>> = pages.iter().map(|_| None).collect();
+    std::thread::scope(|s| {
+        let handles: Vec<_> = (0 .. threads).map(|_| s.spawn(&worker)).collect();
+        for handle in handles {
+            match handle.join() {
+                Ok(results) => for (i, r) in results {
+                    out[i] = Some(r);
+                }
+                Err(panic) => std::panic::resume_unwind(panic)
+            }
+        }
+    });
+    out
+}
+
+/// Render `pages` to bitmaps in parallel.
+///
+/// Pages in progress stop when either `parallel.cancel` or the cancel token of `options` is cancelled.
+pub fn render_raster<P, R>(
+    cache: &Cache,
+    resolve: &R,
+    pages: &[P],
+    transform: Transform2F,
+    options: &RenderOptions,
+    parallel: &ParallelOptions
+) -> Vec<Option<Result<ImageData<'static>, RenderError>>>
+    where P: Deref<Target=Page> + Sync, R: Resolve + Sync
+{
+    let cancel = match options.cancel {
+        Some(ref own) => own.or(&parallel.cancel),
+        None => parallel.cancel.clone()
+    };
+    let options = RenderOptions { cancel: Some(cancel), ..options.clone() };
+    render_parallel(pages, parallel, |_, page| {
+        let mut backend = RasterBackend::new(Some(cache));
+        render_page_with(&mut backend, resolve, &**page, transform, &options)?;
+        Ok(backend.finish())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::Budget;
+    use crate::writer::{PdfBuilder, load_page};
+
+    #[test]
+    fn results_in_page_order() {
+        let pages: Vec<u32> = (0 .. 20).collect();
+        let progress = Mutex::new(vec![]);
+        let report = |done, total| progress.lock().unwrap().push((done, total));
+        let options = ParallelOptions { threads: Some(3), progress: Some(&report), ..ParallelOptions::default() };
+        let results = render_parallel(&pages, &options, |i, &page| if page % 7 == 0 { Err(i) } else { Ok(page * 2) });
+        for (i, result) in results.into_iter().enumerate() {
+            let expected = if i % 7 == 0 { Err(i) } else { Ok(i as u32 * 2) };
+            assert_eq!(result, Some(expected));
+        }
+        let mut progress = progress.into_inner().unwrap();
+        progress.sort();
+        assert_eq!(progress, (1 ..= 20).map(|done| (done, 20)).collect::<Vec<_>>());
+
+        // nothing starts after cancellation
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let options = ParallelOptions { cancel, ..ParallelOptions::default() };
+        let results = render_parallel(&pages, &options, |_, &page| Ok::<_, ()>(page));
+        assert!(results.iter().all(|r| r.is_none()));
+    }
+
+    #[test]
+    fn raster_pages() {
+        let data = PdfBuilder::new().finish(b"
+            1 0 0 rg 10 10 40 40 re f
+            0 0 1 RG 4 w 20 20 m 80 80 l S
+        ");
+        let (file, page) = load_page(data);
+        let resolver = file.resolver();
+        let pages = vec![page.clone(); 5];
+        let cache = Cache::builder().build().unwrap();
+        let transform = Transform2F::from_scale(72. / 25.4);
+        let options = RenderOptions::default();
+
+        let mut backend = RasterBackend::new(Some(&cache));
+        render_page_with(&mut backend, &resolver, &page, transform, &options).unwrap();
+        let expected = backend.finish();
+
+        let parallel = ParallelOptions { threads: Some(3), ..ParallelOptions::default() };
+        let images = render_raster(&cache, &resolver, &pages, transform, &options, &parallel);
+        assert_eq!(images.len(), 5);
+        for image in images {
+            assert_eq!(image.unwrap().unwrap().data(), expected.data());
+        }
+
+        // the token of the render options still stops the pages
+        let own = CancelToken::new();
+        own.cancel();
+        let cancelled = RenderOptions { cancel: Some(own), ..RenderOptions::default() };
+        let images = render_raster(&cache, &resolver, &pages, transform, &cancelled, &ParallelOptions::default());
+        assert!(images.iter().all(|i| matches!(i, Some(Err(RenderError::Cancelled)))));
+
+        // budgets stop each page with their own error
+        let options = RenderOptions { max_ops: Some(1), ..RenderOptions::default() };
+        let images = render_raster(&cache, &resolver, &pages, transform, &options, &ParallelOptions::default());
+        assert!(images.iter().all(|i| matches!(i, Some(Err(RenderError::BudgetExceeded(Budget::Ops(1)))))));
+    }
+}
@@ -0,0 +1,101 @@
+//! Substitutes for the standard 14 fonts that are compiled into the binary (feature `embed-fonts`).
+//!
+//! The fonts are the URW base 35 fonts, which have the same metrics as the standard 14 fonts.
+//! They also serve as sans, serif and monospace fallback for any other font that is not embedded.
+//! See `bundled-fonts/README.md` for how to obtain them.
+
+use crate::font::FontRc;
+use crate::lru::LruCache;
+use crate::provider::{FontProvider, FontQuery, Substitute, Family, parse_name, standard_family, guess_family, font_file_cache, PROVIDER_BUDGET};
+
+pub struct BuiltinFont {
+    pub file: &'static str,
+    pub data: &'static [u8],
+}
+
+macro_rules! font {
+    ($file:literal) => {
+        BuiltinFont {
+            file: $file,
+            data: include_bytes!(concat!(env!("OUT_DIR"), "/fonts/", $file)),
+        }
+    };
+}
+
+// regular, bold, italic, bold italic
+static SANS: [BuiltinFont; 4] = [
+    font!("NimbusSans-Regular.otf"),
+    font!("NimbusSans-Bold.otf"),
+    font!("NimbusSans-Italic.otf"),
+    font!("NimbusSans-BoldItalic.otf"),
+];
+static SERIF: [BuiltinFont; 4] = [
+    font!("NimbusRoman-Regular.otf"),
+    font!("NimbusRoman-Bold.otf"),
+    font!("NimbusRoman-Italic.otf"),
+    font!("NimbusRoman-BoldItalic.otf"),
+];
+static MONO: [BuiltinFont; 4] = [
+    font!("NimbusMonoPS-Regular.otf"),
+    font!("NimbusMonoPS-Bold.otf"),
+    font!("NimbusMonoPS-Italic.otf"),
+    font!("NimbusMonoPS-BoldItalic.otf"),
+];
+static SYMBOL: BuiltinFont = font!("StandardSymbolsPS.otf");
+static DINGBATS: BuiltinFont = font!("D050000L.otf");
+
+pub fn fallback(family: Family, bold: bool, italic: bool) -> &'static BuiltinFont {
+    let fonts = match family {
+        Family::Sans => &SANS,
+        Family::Serif => &SERIF,
+        Family::Mono => &MONO,
+    };
+    &fonts[bold as usize + 2 * italic as usize]
+}
+
+/// The bundled font to use for `query` and whether it is bold and italic.
+/// Unknown fonts get a fallback by family.
+pub fn lookup(query: &FontQuery) -> (&'static BuiltinFont, bool, bool) {
+    let (base, _, _) = parse_name(&query.name);
+    let lower = base.to_ascii_lowercase();
+    match base {
+        "Symbol" => return (&SYMBOL, query.is_bold(), query.italic),
+        "ZapfDingbats" => return (&DINGBATS, query.is_bold(), query.italic),
+        // symbolic fonts have their own glyph sets, a text font would show the wrong glyphs
+        _ if query.is_symbolic() && lower.contains("ding") => return (&DINGBATS, query.is_bold(), query.italic),
+        _ if query.is_symbolic() && (lower.contains("symbol") || lower.contains("math")) => return (&SYMBOL, query.is_bold(), query.italic),
+        _ => {}
+    }
+    let family = standard_family(base)
+        .or_else(|| standard_family(&query.family))
+        .unwrap_or_else(|| guess_family(base, query));
+    (fallback(family, query.is_bold(), query.italic), query.is_bold(), query.italic)
+}
+
+/// Always has a font, so it should be the last provider.
+pub struct BuiltinProvider {
+    cache: LruCache<&'static str, Option<FontRc>>,
+}
+impl BuiltinProvider {
+    pub fn new() -> Self {
+        BuiltinProvider { cache: font_file_cache(PROVIDER_BUDGET) }
+    }
+}
+impl Default for BuiltinProvider {
+    fn default() -> Self {
+        BuiltinProvider::new()
+    }
+}
+impl FontProvider for BuiltinProvider {
+    fn get_font(&self, query: &FontQuery) -> Option<Substitute> {
+        let (file, bold, italic) = lookup(query);
+        let font = self.cache.get(file.file, || match font::parse(file.data) {
+            Ok(f) => Some(f.into()),
+            Err(e) => {
+                warn!("Font Error in bundled {}: {:?}", file.file, e);
+                None
+            }
+        })?.0;
+        Some(Substitute { font, name: file.file.into(), bold, italic })
+    }
+}
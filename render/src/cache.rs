@@ -1,5 +1,5 @@
-use std::path::{PathBuf};
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 use pdf::object::*;
 use pdf::primitive::Name;
@@ -14,9 +14,13 @@ use pathfinder_content::{
 };
 
 use super::{fontentry::FontEntry};
-use super::image::load_image;
-use super::font::{load_font, StandardCache};
-use globalcache::{sync::SyncCache, ValueSize};
+use super::image::{load_image_level, scale_level};
+use super::font::{load_font, StandardCache, DumpFonts, LogSink};
+use super::provider::{FontProvider, PROVIDER_BUDGET};
+use super::report::{FontReport, LoadedFont};
+use super::glyphcache::GlyphCache;
+use super::lru::{LruCache, CacheStats};
+use globalcache::ValueSize;
 
 #[derive(Clone)]
 pub struct ImageResult(pub Arc<Result<Image>>);
@@ -29,40 +33,204 @@ impl ValueSize for ImageResult {
     }
 }
 
+/// Byte budgets of the caches. The least recently used entries are dropped when a cache exceeds its budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheLimits {
+    pub fonts: usize,
+    pub images: usize,
+    pub glyphs: usize,
+    /// parsed substitute fonts kept by the font directory and the system font provider, each
+    pub providers: usize,
+}
+impl Default for CacheLimits {
+    fn default() -> Self {
+        CacheLimits {
+            fonts: 64 << 20,
+            images: 256 << 20,
+            glyphs: 32 << 20,
+            providers: PROVIDER_BUDGET,
+        }
+    }
+}
+
+/// Usage of the caches of a `Cache`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceStats {
+    pub fonts: CacheStats,
+    pub images: CacheStats,
+    pub glyphs: CacheStats,
+}
+
+/// Configuration for `Cache`.
+#[derive(Clone, Default)]
+pub struct CacheBuilder {
+    pub(crate) font_dir: Option<PathBuf>,
+    pub(crate) dump: DumpFonts,
+    pub(crate) dump_dir: Option<PathBuf>,
+    pub(crate) sink: Option<LogSink>,
+    pub(crate) providers: Vec<Arc<dyn FontProvider>>,
+    pub(crate) system_fonts: bool,
+    pub(crate) glyph_cache: Option<GlyphCache>,
+    pub(crate) limits: CacheLimits,
+}
+impl CacheBuilder {
+    /// No standard fonts, no font dumps and messages go to the `log` crate.
+    pub fn new() -> Self {
+        CacheBuilder::default()
+    }
+    /// Configuration from the environment: the fonts in `STANDARD_FONTS` (or `fonts/` if it has a `fonts.json`)
+    /// and font dumps into the working directory as set by `DUMP_FONT` (`always` or `error`).
+    pub fn from_env() -> Self {
+        let font_dir = match std::env::var_os("STANDARD_FONTS") {
+            Some(path) => Some(PathBuf::from(path)),
+            None if Path::new("fonts/fonts.json").is_file() => {
+                info!("STANDARD_FONTS not set. using fonts/ instead.");
+                Some(PathBuf::from("fonts"))
+            }
+            None => {
+                warn!("STANDARD_FONTS not set. Fonts that are not embedded will be missing.");
+                None
+            }
+        };
+        let dump = match std::env::var("DUMP_FONT").as_deref() {
+            Ok("always") => DumpFonts::Always,
+            Ok("error") => DumpFonts::OnError,
+            _ => DumpFonts::Never
+        };
+        CacheBuilder { font_dir, dump, ..CacheBuilder::default() }
+    }
+    /// Directory with the standard fonts and a `fonts.json` that maps font names to file names.
+    pub fn font_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.font_dir = Some(dir.into());
+        self
+    }
+    /// Write embedded fonts into `dir`.
+    pub fn dump_fonts(mut self, when: DumpFonts, dir: impl Into<PathBuf>) -> Self {
+        self.dump = when;
+        self.dump_dir = Some(dir.into());
+        self
+    }
+    /// Ask `provider` for fonts that are not embedded, after the font directory and previously added providers.
+    pub fn font_provider(mut self, provider: impl FontProvider + 'static) -> Self {
+        self.providers.push(Arc::new(provider));
+        self
+    }
+    /// Look for fonts that are not embedded in the system font directories, after all other providers.
+    pub fn system_fonts(mut self, enable: bool) -> Self {
+        self.system_fonts = enable;
+        self
+    }
+    /// Byte budgets for fonts, images, glyphs and font providers. The glyph budget is ignored if a `glyph_cache` is set.
+    pub fn limits(mut self, limits: CacheLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+    /// Store glyph outlines in `cache`, to share them with other caches.
+    pub fn glyph_cache(mut self, cache: GlyphCache) -> Self {
+        self.glyph_cache = Some(cache);
+        self
+    }
+    pub fn log_sink(mut self, sink: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.sink = Some(Arc::new(sink));
+        self
+    }
+    /// Fails if the font directory has no valid `fonts.json`.
+    pub fn build(&self) -> Result<Cache> {
+        Ok(Cache {
+            fonts: LruCache::new(self.limits.fonts, font_size),
+            images: LruCache::new(self.limits.images, |im: &ImageResult| im.size()),
+            std: StandardCache::new(self)?,
+            loaded_fonts: Mutex::default(),
+            missing_fonts: Mutex::default(),
+        })
+    }
+}
+
+fn font_size((_, entry): &(MaybeRef<PdfFont>, Option<Arc<FontEntry>>)) -> usize {
+    entry.as_ref().map(|e| e.size()).unwrap_or(1)
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum FontKey {
+    Ref(PlainRef),
+    /// address of a font dictionary that is not an indirect object, kept alive by the cache entry
+    Direct(usize),
+}
+impl FontKey {
+    fn new(font: &MaybeRef<PdfFont>) -> Self {
+        match *font {
+            MaybeRef::Indirect(ref r) => FontKey::Ref(r.get_ref().get_inner()),
+            MaybeRef::Direct(ref font) => FontKey::Direct(Arc::as_ptr(font) as usize),
+        }
+    }
+}
+
+/// Fonts, images and glyph outlines used while rendering.
+///
+/// All methods take `&self`, so one cache can be shared (for example in an `Arc`)
+/// by all backends, including backends on other threads rendering different pages.
+///
+/// Fonts and images are keyed by their object reference, so a cache belongs to one document.
+/// To share glyph outlines between documents, pass the `glyph_cache` of one cache to the builder of the others.
 pub struct Cache {
-    // shared mapping of fontname -> font
-    fonts: Arc<SyncCache<usize, Option<Arc<FontEntry>>>>,
-    images: Arc<SyncCache<Ref<XObject>, ImageResult>>,
+    // the pdf font is kept so the address of a direct font is not reused while it is a key
+    fonts: LruCache<FontKey, (MaybeRef<PdfFont>, Option<Arc<FontEntry>>)>,
+    // keyed by the reference and the resolution level
+    images: LruCache<(Ref<XObject>, u32), ImageResult>,
     std: StandardCache,
-    missing_fonts: Vec<Name>,
+    // one entry per font, even if it was loaded again after eviction
+    loaded_fonts: Mutex<Vec<(FontKey, LoadedFont)>>,
+    missing_fonts: Mutex<Vec<Name>>,
 }
 impl Cache {
-    pub fn new() -> Cache {
-        let standard_fonts;
-        if let Some(path) = std::env::var_os("STANDARD_FONTS") {
-            standard_fonts = PathBuf::from(path);
-        } else {
-            eprintln!("PDF: STANDARD_FONTS not set. using fonts/ instead.");
-            standard_fonts = PathBuf::from("fonts");
-        }
-        if !standard_fonts.is_dir() {
-            panic!("STANDARD_FONTS (or fonts/) is not directory.");
-        }
-        Cache {
-            fonts: SyncCache::new(),
-            images: SyncCache::new(),
-            std: StandardCache::new(standard_fonts),
-            missing_fonts: Vec::new(),
+    /// Same as `CacheBuilder::from_env().build()`.
+    pub fn new() -> Result<Cache> {
+        CacheBuilder::from_env().build()
+    }
+    pub fn builder() -> CacheBuilder {
+        CacheBuilder::new()
+    }
+    /// Fonts that were neither embedded nor found in the font directory.
+    pub fn missing_fonts(&self) -> Vec<Name> {
+        self.missing_fonts.lock().unwrap().clone()
+    }
+    pub fn stats(&self) -> ResourceStats {
+        ResourceStats {
+            fonts: self.fonts.stats(),
+            images: self.images.stats(),
+            glyphs: self.std.glyphs.stats(),
         }
     }
-    pub fn get_font(&mut self, pdf_font: &MaybeRef<PdfFont>, resolve: &impl Resolve) -> Result<Option<Arc<FontEntry>>, > {
+    /// Pass to `CacheBuilder::glyph_cache` to share glyph outlines with another cache.
+    pub fn glyph_cache(&self) -> GlyphCache {
+        self.std.glyphs.clone()
+    }
+    /// All fonts loaded so far.
+    pub fn font_report(&self) -> FontReport {
+        let fonts = self.loaded_fonts.lock().unwrap();
+        let missing = self.missing_fonts.lock().unwrap();
+        FontReport::new(fonts.iter().map(|(_, font)| font), missing.iter().map(|n| n.as_str().into()))
+    }
+    pub fn get_font(&self, pdf_font: &MaybeRef<PdfFont>, resolve: &impl Resolve) -> Result<Option<Arc<FontEntry>>> {
         let mut error = None;
-        let val = self.fonts.get(&**pdf_font as *const PdfFont as usize, || 
-            match load_font(pdf_font, resolve, &mut self.std) {
-                Ok(Some(f)) => Some(Arc::new(f)),
+        let key = FontKey::new(pdf_font);
+        let (_, val) = self.fonts.get(key.clone(), || {
+            let entry = match load_font(pdf_font, resolve, &self.std) {
+                Ok(Some(mut f)) => {
+                    let mut loaded = self.loaded_fonts.lock().unwrap();
+                    // loaded by another thread at the same time or before it was evicted
+                    if !loaded.iter().any(|(k, font)| *k == key && font.adopt(&mut f)) {
+                        loaded.push((key.clone(), LoadedFont::new(&f)));
+                    }
+                    Some(Arc::new(f))
+                }
                 Ok(None) => {
                     if let Some(ref name) = pdf_font.name {
-                        self.missing_fonts.push(name.clone());
+                        let mut missing = self.missing_fonts.lock().unwrap();
+                        if !missing.contains(name) {
+                            self.std.log(&format!("missing font {}", name.as_str()));
+                            missing.push(name.clone());
+                        }
                     }
                     None
                 },
@@ -70,27 +238,46 @@ impl Cache {
                     error = Some(e);
                     None
                 }
-            }
-        );
+            };
+            (pdf_font.clone(), entry)
+        });
         match error {
             None => Ok(val),
             Some(e) => Err(e)
         }
     }
 
-    pub fn get_image(&mut self, xobject_ref: Ref<XObject>, im: &ImageXObject, resources: &Resources, resolve: &impl Resolve) -> ImageResult {
-        self.images.get(xobject_ref, ||
-            ImageResult(Arc::new(load_image(im, resources, resolve).map(|image|
-                Image::new(Vector2I::new(im.width as i32, im.height as i32), Arc::new(image.into_data().into()))
-            )))
+    pub fn get_image(&self, xobject_ref: Ref<XObject>, im: &ImageXObject, resources: &Resources, resolve: &impl Resolve) -> ImageResult {
+        self.get_image_level(xobject_ref, im, resources, resolve, 0)
+    }
+    /// `im` decoded at the smallest resolution level that still covers `size` device pixels.
+    /// Each level is cached separately.
+    pub fn get_image_scaled(&self, xobject_ref: Ref<XObject>, im: &ImageXObject, resources: &Resources, resolve: &impl Resolve, size: (u32, u32)) -> ImageResult {
+        let level = scale_level(im.width as u32, im.height as u32, size);
+        self.get_image_level(xobject_ref, im, resources, resolve, level)
+    }
+    fn get_image_level(&self, xobject_ref: Ref<XObject>, im: &ImageXObject, resources: &Resources, resolve: &impl Resolve, level: u32) -> ImageResult {
+        self.images.get((xobject_ref, level), ||
+            ImageResult(Arc::new(load_image_level(im, resources, resolve, level).map(|image| {
+                let size = Vector2I::new(image.width() as i32, image.height() as i32);
+                Image::new(size, Arc::new(image.into_data().into()))
+            })))
         )
     }
 }
-impl Drop for Cache {
-    fn drop(&mut self) {
-        println!("missing fonts:");
-        for name in self.missing_fonts.iter() {
-            println!("{}", name.as_str());
-        }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder() {
+        assert!(Cache::builder().build().is_ok());
+
+        let dir = std::env::temp_dir().join(format!("pdf_render_no_fonts_json_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let without_fonts_json = Cache::builder().font_dir(&dir).build();
+        std::fs::remove_dir(&dir).unwrap();
+        assert!(without_fonts_json.is_err());
     }
 }
@@ -0,0 +1,55 @@
+use pdf::content::Op;
+use pdf::error::PdfError;
+
+/// An operator that failed and was skipped in lenient mode.
+///
+/// Failures with the same operator and error kind are counted in a single entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// index of the first failing operator within its content stream
+    pub op_nr: usize,
+    /// operator name, like `Fill` or `TextDraw`
+    pub operator: String,
+    /// error variant, like `NotFound` or `Other`
+    pub kind: String,
+    /// message of the first error
+    pub message: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    entries: Vec<Diagnostic>,
+}
+impl Diagnostics {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item=&Diagnostic> {
+        self.entries.iter()
+    }
+    /// number of skipped operators
+    pub fn total(&self) -> usize {
+        self.entries.iter().map(|d| d.count).sum()
+    }
+    pub(crate) fn add(&mut self, op_nr: usize, op: &Op, error: &PdfError) {
+        let operator = variant_name(op);
+        let kind = variant_name(error);
+        match self.entries.iter_mut().find(|d| d.operator == operator && d.kind == kind) {
+            Some(entry) => entry.count += 1,
+            None => self.entries.push(Diagnostic {
+                op_nr,
+                operator,
+                kind,
+                message: error.to_string(),
+                count: 1,
+            })
+        }
+    }
+}
+
+/// the enum variant from the debug representation
+fn variant_name(value: &impl std::fmt::Debug) -> String {
+    let s = format!("{:?}", value);
+    s.split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap_or_default().to_owned()
+}
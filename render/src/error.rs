@@ -0,0 +1,55 @@
+use std::fmt;
+use std::time::Duration;
+use pdf::error::PdfError;
+
+/// The limit of `RenderOptions` that stopped rendering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Ops(usize),
+    Time(Duration),
+    /// bytes of image data and path points
+    Memory(usize),
+}
+
+/// Error of `render_page_with`.
+///
+/// The backend keeps everything drawn before rendering stopped,
+/// so it can still be used as partial output after `Cancelled` and `BudgetExceeded`.
+#[derive(Debug)]
+pub enum RenderError {
+    Pdf(PdfError),
+    /// the `CancelToken` of the options was cancelled
+    Cancelled,
+    BudgetExceeded(Budget),
+}
+impl RenderError {
+    /// true if rendering was stopped before the page was complete, not because of an error in the file
+    pub fn is_interrupted(&self) -> bool {
+        !matches!(self, RenderError::Pdf(_))
+    }
+}
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenderError::Pdf(ref e) => e.fmt(f),
+            RenderError::Cancelled => write!(f, "rendering cancelled"),
+            RenderError::BudgetExceeded(Budget::Ops(n)) => write!(f, "operator budget of {} exceeded", n),
+            RenderError::BudgetExceeded(Budget::Time(t)) => write!(f, "time budget of {:?} exceeded", t),
+            RenderError::BudgetExceeded(Budget::Memory(n)) => write!(f, "memory budget of {} bytes exceeded", n),
+        }
+    }
+}
+impl std::error::Error for RenderError {}
+impl From<PdfError> for RenderError {
+    fn from(e: PdfError) -> Self {
+        RenderError::Pdf(e)
+    }
+}
+impl From<RenderError> for PdfError {
+    fn from(e: RenderError) -> Self {
+        match e {
+            RenderError::Pdf(e) => e,
+            e => PdfError::Other { msg: e.to_string() }
+        }
+    }
+}
@@ -1,6 +1,5 @@
 use std::path::{PathBuf};
 use std::ops::Deref;
-use std::collections::HashMap;
 use pdf::object::*;
 use pdf::font::{Font as PdfFont};
 use pdf::error::{Result, PdfError};
@@ -8,15 +7,22 @@ use pdf::error::{Result, PdfError};
 use font::{self};
 use std::sync::Arc;
 use super::FontEntry;
-use globalcache::{sync::SyncCache, ValueSize};
+use super::fontentry::Substitution;
+use super::cache::CacheBuilder;
+use super::glyphcache::GlyphCache;
+use super::provider::{FontProvider, FontQuery, Substitute, DirectoryProvider, SystemFontProvider};
+#[cfg(feature = "embed-fonts")]
+use super::builtin::BuiltinProvider;
+use globalcache::ValueSize;
 use std::hash::{Hash, Hasher};
 
 #[derive(Clone)]
-pub struct FontRc(Arc<dyn font::Font + Send + Sync + 'static>);
+pub struct FontRc(pub(crate) Arc<dyn font::Font + Send + Sync + 'static>);
 impl ValueSize for FontRc {
     #[inline]
     fn size(&self) -> usize {
-        1 // TODO
+        // rough estimate, the parsed font does not report its size
+        64 * self.num_glyphs() as usize
     }
 }
 impl From<Box<dyn font::Font + Send + Sync + 'static>> for FontRc {
@@ -45,38 +51,68 @@ impl Hash for FontRc {
         Arc::as_ptr(&self.0).hash(state)
     }
 }
+// stroke width in em and horizontal shear for synthesized styles
+const SYNTHETIC_BOLD: f32 = 0.03;
+const SYNTHETIC_ITALIC: f32 = 0.2;
+
+/// Receives messages meant for the user of the library, like dumped or missing fonts.
+pub type LogSink = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// When to write embedded font programs to the dump directory.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum DumpFonts {
+    #[default]
+    Never,
+    OnError,
+    Always
+}
+
 pub struct StandardCache {
-    inner: Arc<SyncCache<String, Option<FontRc>>>,
-    dir: PathBuf,
-    fonts: HashMap<String, String>,
-    dump: Dump,
+    providers: Vec<Arc<dyn FontProvider>>,
+    directory: Option<Arc<DirectoryProvider>>,
+    dump: DumpFonts,
+    dump_dir: PathBuf,
+    sink: Option<LogSink>,
+    pub(crate) glyphs: GlyphCache,
 }
 impl StandardCache {
-    pub fn new(dir: PathBuf) -> Self {
-        let data = std::fs::read_to_string(dir.join("fonts.json")).expect("can't read fonts.json");
-        let fonts: HashMap<String, String> = serde_json::from_str(&data).expect("fonts.json is invalid");
-
-        let dump = match dbg!(std::env::var("DUMP_FONT").as_deref()) {
-            Err(_) => Dump::Never,
-            Ok("always") => Dump::Always,
-            Ok("error") => Dump::OnError,
-            Ok(_) => Dump::Never
+    pub fn new(config: &CacheBuilder) -> Result<Self> {
+        let directory = match config.font_dir {
+            Some(ref dir) => Some(Arc::new(DirectoryProvider::new(dir)?.budget(config.limits.providers))),
+            None => None
         };
-        dbg!(&dump);
-        StandardCache {
-            inner: SyncCache::new(),
-            dir,
-            fonts,
-            dump
+        let mut providers: Vec<Arc<dyn FontProvider>> = vec![];
+        if let Some(ref directory) = directory {
+            providers.push(directory.clone());
         }
-    }
-}
+        providers.extend(config.providers.iter().cloned());
+        if config.system_fonts {
+            providers.push(Arc::new(SystemFontProvider::new().budget(config.limits.providers)));
+        }
+        #[cfg(feature = "embed-fonts")]
+        providers.push(Arc::new(BuiltinProvider::new()));
 
-#[derive(Debug)]
-enum Dump {
-    Never,
-    OnError,
-    Always
+        Ok(StandardCache {
+            providers,
+            directory,
+            dump: config.dump,
+            dump_dir: config.dump_dir.clone().unwrap_or_default(),
+            sink: config.sink.clone(),
+            glyphs: config.glyph_cache.clone().unwrap_or_else(|| GlyphCache::with_budget(config.limits.glyphs)),
+        })
+    }
+    pub fn log(&self, msg: &str) {
+        match self.sink {
+            Some(ref sink) => sink(msg),
+            None => info!("{}", msg)
+        }
+    }
+    /// A replacement for a font that is not embedded: the first match of the providers,
+    /// otherwise a font of the same family from the font directory.
+    fn get(&self, query: &FontQuery) -> Option<Substitute> {
+        self.providers.iter().find_map(|p| p.get_font(query))
+            .or_else(|| self.directory.as_ref()?.fallback_for(query))
+    }
 }
 
 pub fn load_font(font_ref: &MaybeRef<PdfFont>, resolve: &impl Resolve, cache: &StandardCache) -> Result<Option<FontEntry>> {
@@ -89,53 +125,43 @@ pub fn load_font(font_ref: &MaybeRef<PdfFont>, resolve: &impl Resolve, cache: &S
             let font = font::parse(&data).map_err(|e| {
                 PdfError::Other { msg: format!("Font Error: {:?}", e) }
             });
-            if matches!(cache.dump, Dump::Always) || (matches!(cache.dump, Dump::OnError) && font.is_err()) {
-                let name = format!("font_{}", pdf_font.name.as_ref().map(|s| s.as_str()).unwrap_or("unnamed"));
-                std::fs::write(&name, &data).unwrap();
-                println!("font dumped in {}", name);
+            if cache.dump == DumpFonts::Always || (cache.dump == DumpFonts::OnError && font.is_err()) {
+                let path = cache.dump_dir.join(format!("font_{}", pdf_font.name.as_ref().map(|s| s.as_str()).unwrap_or("unnamed")));
+                match std::fs::write(&path, &data) {
+                    Ok(()) => cache.log(&format!("font dumped in {}", path.display())),
+                    Err(e) => warn!("can't dump font to {}: {}", path.display(), e)
+                }
             }
             FontRc::from(font?)
         }
         Some(Err(e)) => return Err(e),
         None => {
             debug!("no embedded font.");
-            let name = match pdf_font.name {
-                Some(ref name) => name.as_str(),
+            let query = match FontQuery::from_font(&pdf_font) {
+                Some(query) => query,
                 None => return Ok(None)
             };
-            debug!("loading {name} instead");
-            match cache.fonts.get(name).or_else(|| cache.fonts.get("Arial")) {
-                Some(file_name) => {
-                    let val = cache.inner.get(file_name.clone(), || {
-                        let data = match std::fs::read(cache.dir.join(file_name)) {
-                            Ok(data) => data,
-                            Err(e) => {
-                                warn!("can't open {} for {:?} {:?}", file_name, pdf_font.name, e);
-                                return None;
-                            }
-                        };
-                        match font::parse(&data) {
-                            Ok(f) => Some(f.into()),
-                            Err(e) => {
-                                warn!("Font Error: {:?}", e);
-                                return None;
-                            }
-                        }
-                    });
-                    match val {
-                        Some(f) => f,
-                        None => {
-                            return Ok(None);
-                        }
-                    }
-                }
+            debug!("loading {} instead", query.name);
+            let substitute = match cache.get(&query) {
+                Some(f) => f,
                 None => {
                     warn!("no font for {:?}", pdf_font.name);
                     return Ok(None);
                 }
-            }
+            };
+            let mut entry = FontEntry::build(FontRc(substitute.font), pdf_font, resolve)?;
+            entry.substitute = Some(substitute.name);
+            entry.glyphs = Some(cache.glyphs.clone());
+            entry.substitution = Some(Substitution {
+                scale_to_widths: true,
+                embolden: if query.is_bold() && !substitute.bold { SYNTHETIC_BOLD } else { 0.0 },
+                shear: if query.italic && !substitute.italic { SYNTHETIC_ITALIC } else { 0.0 },
+            });
+            return Ok(Some(entry));
         }
     };
 
-    Ok(Some(FontEntry::build(font, pdf_font, resolve)?))
+    let mut entry = FontEntry::build(font, pdf_font, resolve)?;
+    entry.glyphs = Some(cache.glyphs.clone());
+    Ok(Some(entry))
 }
@@ -1,12 +1,16 @@
 use std::collections::HashMap;
-use font::{self, GlyphId, TrueTypeFont, CffFont, Type1Font, OpenTypeFont};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use font::{self, Glyph, GlyphId, TrueTypeFont, CffFont, Type1Font, OpenTypeFont};
 use pdf::encoding::BaseEncoding;
 use pdf::font::{Font as PdfFont, Widths, CidToGidMap};
 use pdf::object::{Resolve, MaybeRef};
 use pdf::error::PdfError;
 use pdf_encoding::{Encoding, glyphname_to_unicode};
 use istring::SmallString;
+use globalcache::ValueSize;
 use crate::font::FontRc;
+use crate::glyphcache::GlyphCache;
 
 #[derive(Debug)]
 pub enum TextEncoding {
@@ -14,6 +18,17 @@ pub enum TextEncoding {
     Cmap(HashMap<u16, (GlyphId, Option<SmallString>)>)
 }
 
+/// How a font that is not embedded is imitated by its substitute.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Substitution {
+    /// scale glyphs horizontally so their advance matches `/Widths`
+    pub scale_to_widths: bool,
+    /// stroke width in em to embolden glyphs, 0 for none
+    pub embolden: f32,
+    /// horizontal shear to slant glyphs, 0 for none
+    pub shear: f32,
+}
+
 pub struct FontEntry {
     pub font: FontRc,
     pub pdf_font: MaybeRef<PdfFont>,
@@ -21,6 +36,14 @@ pub struct FontEntry {
     pub widths: Option<Widths>,
     pub is_cid: bool,
     pub name: String,
+    /// `None` for embedded fonts
+    pub substitution: Option<Substitution>,
+    /// name of the substitute font if not embedded
+    pub substitute: Option<String>,
+    pub has_to_unicode: bool,
+    // shared with the font report, which outlives the entry
+    pub(crate) fallback_glyphs: Arc<AtomicUsize>,
+    pub(crate) glyphs: Option<GlyphCache>,
 }
 impl FontEntry {
     pub fn build(font: FontRc, pdf_font: MaybeRef<PdfFont>, resolve: &impl Resolve) -> Result<FontEntry, PdfError> {
@@ -50,7 +73,7 @@ impl FontEntry {
         let build_map = || {
             if let Some(ref to_unicode) = to_unicode {
                 let mut num1 = 0;
-                dbg!(font.encoding());
+                debug!("encoding: {:?}", font.encoding());
                 let mut map: HashMap<_, _> = to_unicode.iter().map(|(cid, s)| {
                     let gid = font.gid_for_codepoint(cid as u32);
                     if gid.is_some() {
@@ -215,12 +238,41 @@ impl FontEntry {
             is_cid,
             widths,
             name,
+            substitution: None,
+            substitute: None,
+            has_to_unicode: to_unicode.is_some(),
+            fallback_glyphs: Arc::default(),
+            glyphs: None,
         })
     }
+    /// The outline of `gid`, from the glyph cache if the entry was loaded by a cache.
+    pub fn glyph(&self, gid: GlyphId) -> Option<Arc<Glyph>> {
+        match self.glyphs {
+            Some(ref cache) => cache.get(&self.font, gid),
+            None => self.font.glyph(gid).map(Arc::new)
+        }
+    }
+    /// number of glyphs drawn with the character code as glyph id, because the encoding had no glyph for it
+    pub fn fallback_glyphs(&self) -> usize {
+        self.fallback_glyphs.load(Ordering::Relaxed)
+    }
+    pub(crate) fn count_fallback_glyph(&self) {
+        self.fallback_glyphs.fetch_add(1, Ordering::Relaxed);
+    }
 }
 
-impl globalcache::ValueSize for FontEntry {
+impl ValueSize for FontEntry {
     fn size(&self) -> usize {
-        1 // TODO
+        // substitutes are accounted in the font cache of their provider
+        let program = match self.substitution {
+            None => ValueSize::size(&self.font),
+            Some(_) => 0
+        };
+        let encoding = match self.encoding {
+            TextEncoding::CID(Some(ref map)) => map.len() * std::mem::size_of::<(u16, (Option<GlyphId>, SmallString))>(),
+            TextEncoding::CID(None) => 0,
+            TextEncoding::Cmap(ref map) => map.len() * std::mem::size_of::<(u16, (GlyphId, Option<SmallString>))>(),
+        };
+        std::mem::size_of::<FontEntry>() + program + encoding
     }
 }
@@ -0,0 +1,142 @@
+use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+use std::sync::{Arc, Weak};
+use font::{Glyph, GlyphId};
+use pathfinder_content::outline::Contour;
+use pathfinder_geometry::vector::Vector2F;
+use globalcache::ValueSize;
+use crate::font::FontRc;
+use crate::cache::CacheLimits;
+use crate::lru::{LruCache, CacheStats};
+
+#[derive(Clone)]
+pub struct GlyphRc(pub Arc<Glyph>);
+impl ValueSize for GlyphRc {
+    fn size(&self) -> usize {
+        outline_size(&self.0)
+    }
+}
+
+/// bytes used by the outline of `glyph`
+fn outline_size(glyph: &Glyph) -> usize {
+    size_of::<Glyph>() + glyph.path.contours().iter().map(|c| {
+        // points and flags
+        size_of::<Contour>() + c.len() as usize * (size_of::<Vector2F>() + 1)
+    }).sum::<usize>()
+}
+
+/// Identifies a font without keeping it alive.
+///
+/// The weak reference keeps only the allocation, so its address is not reused while the key exists.
+#[derive(Clone)]
+struct FontId(Weak<dyn font::Font + Send + Sync + 'static>);
+impl FontId {
+    fn new(font: &FontRc) -> Self {
+        FontId(Arc::downgrade(&font.0))
+    }
+    fn addr(&self) -> *const () {
+        self.0.as_ptr() as *const ()
+    }
+}
+impl PartialEq for FontId {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr() == other.addr()
+    }
+}
+impl Eq for FontId {}
+impl Hash for FontId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.addr().hash(state)
+    }
+}
+
+/// Glyph outlines by font and glyph id.
+///
+/// Cached glyphs do not keep their font alive; the outlines of dropped fonts are evicted like any other.
+/// Clones share the same storage, so one `GlyphCache` can be used by several `Cache`s.
+#[derive(Clone)]
+pub struct GlyphCache {
+    glyphs: Arc<LruCache<(FontId, GlyphId), Option<GlyphRc>>>,
+}
+impl GlyphCache {
+    /// With the default budget of `CacheLimits`.
+    pub fn new() -> Self {
+        GlyphCache::with_budget(CacheLimits::default().glyphs)
+    }
+    /// Keep at most `bytes` of outlines.
+    pub fn with_budget(bytes: usize) -> Self {
+        GlyphCache { glyphs: Arc::new(LruCache::new(bytes, |g: &Option<GlyphRc>| g.as_ref().map(|g| g.size()).unwrap_or(1))) }
+    }
+    pub fn stats(&self) -> CacheStats {
+        self.glyphs.stats()
+    }
+    pub fn get(&self, font: &FontRc, gid: GlyphId) -> Option<Arc<Glyph>> {
+        self.glyphs.get((FontId::new(font), gid), || font.glyph(gid).map(|g| GlyphRc(Arc::new(g))))
+            .map(|g| g.0)
+    }
+}
+impl Default for GlyphCache {
+    fn default() -> Self {
+        GlyphCache::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdf::file::FileOptions;
+
+    /// the embedded fonts of the first page of the sample document
+    fn sample_fonts() -> Vec<FontRc> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/compressed.tracemonkey-pldi-09.pdf");
+        let file = FileOptions::cached().open(path).unwrap();
+        let resolver = file.resolver();
+        let page = file.get_page(0).unwrap();
+        let resources = page.resources().unwrap();
+        resources.fonts.values()
+            .filter_map(|font| font.embedded_data(&resolver)?.ok())
+            .map(|data| FontRc::from(font::parse(&data).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn outlines_are_reused() {
+        let fonts = sample_fonts();
+        let cache = GlyphCache::new();
+        let a = cache.get(&fonts[0], GlyphId(1));
+        let b = cache.get(&fonts[0], GlyphId(1));
+        assert!(a.is_some());
+        assert_eq!(a.as_ref().map(Arc::as_ptr), b.as_ref().map(Arc::as_ptr));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+    }
+
+    #[test]
+    fn keyed_by_font_and_glyph() {
+        let fonts = sample_fonts();
+        assert!(fonts.len() >= 2);
+        let cache = GlyphCache::new();
+        let a = cache.get(&fonts[0], GlyphId(1));
+        cache.get(&fonts[0], GlyphId(2));
+        let b = cache.get(&fonts[1], GlyphId(1));
+        assert_ne!(a.as_ref().map(Arc::as_ptr), b.as_ref().map(Arc::as_ptr));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (0, 3, 3));
+
+        // clones share the outlines
+        let shared = cache.clone();
+        let c = shared.get(&fonts[1], GlyphId(1));
+        assert_eq!(b.as_ref().map(Arc::as_ptr), c.as_ref().map(Arc::as_ptr));
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn fonts_are_not_kept_alive() {
+        let font = sample_fonts().remove(0);
+        let weak = Arc::downgrade(&font.0);
+        let cache = GlyphCache::new();
+        assert!(cache.get(&font, GlyphId(1)).is_some());
+        drop(font);
+        assert!(weak.upgrade().is_none());
+    }
+}
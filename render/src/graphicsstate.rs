@@ -1,6 +1,6 @@
 use pdf::object::ColorSpace;
 
-use pathfinder_geometry::transform2d::Transform2F;
+use pathfinder_geometry::{transform2d::Transform2F, rect::RectF};
 use pathfinder_content::{
     fill::FillRule,
     stroke::{StrokeStyle},
@@ -26,6 +26,8 @@ pub struct GraphicsState<'a> {
     pub stroke_paint: Option<PaintId>,
     pub clip_path: Option<ClipPath>,
     pub clip_path_id: Option<ClipPathId>,
+    /// device space rectangle every clip path is limited to, see `intersect_clip_rect`
+    pub clip_rect: Option<RectF>,
     pub fill_color_space: &'a ColorSpace,
     pub stroke_color_space: &'a ColorSpace,
     pub dash_pattern: Option<(&'a [f32], f32)>,
@@ -69,10 +71,10 @@ impl<'a> GraphicsState<'a> {
     pub fn merge_clip_path(&mut self, outline: Outline, fill_rule: FillRule) {
         /*
         if let Some(ref outer) = self.clip_path {
-            println!("path a: {:?}", outline);
+            debug!("path a: {:?}", outline);
             let mut clipped_outline = Outline::new();
             for outer_contour in outer.outline().contours() {
-                println!("path b: {:?}", outer_contour);
+                debug!("path b: {:?}", outer_contour);
                 let clip_polygon = outer_contour.points();
                 let mut clipped = outline.clone();
                 clipped.clip_against_polygon(clip_polygon);
@@ -81,10 +83,38 @@ impl<'a> GraphicsState<'a> {
             outline = clipped_outline;
         }
         */
+        let mut outline = outline;
+        if let Some(rect) = self.clip_rect {
+            outline.clip_against_rect(rect);
+        }
         let mut clip_path = ClipPath::new(outline);
         clip_path.set_fill_rule(fill_rule);
         self.clip_path = Some(clip_path);
     }
+    /// Intersect the clip with `rect` in device space.
+    ///
+    /// Unlike `merge_clip_path`, the rectangle also limits all clip paths set later in this state.
+    pub fn intersect_clip_rect(&mut self, rect: RectF) {
+        let rect = match self.clip_rect {
+            Some(outer) => outer.intersection(rect).unwrap_or_default(),
+            None => rect
+        };
+        self.clip_rect = Some(rect);
+        match self.clip_path {
+            Some(ref clip_path) => {
+                let mut outline = clip_path.outline.clone();
+                outline.clip_against_rect(rect);
+                let mut clipped = ClipPath::new(outline);
+                clipped.set_fill_rule(clip_path.fill_rule());
+                self.clip_path = Some(clipped);
+            }
+            None => self.clip_path = Some(ClipPath::new(Outline::from_rect(rect)))
+        }
+    }
+    /// device space bounds of the clip path
+    pub fn clip_bounds(&self) -> Option<RectF> {
+        self.clip_path.as_ref().map(|c| c.outline.bounds())
+    }
     pub fn stroke(&self) -> Stroke {
         Stroke {
             style: self.stroke_style,
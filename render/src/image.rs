@@ -1,7 +1,9 @@
 use image::{RgbaImage, ImageBuffer, Rgba};
 use pdf::object::*;
 use pdf::error::PdfError;
+use pdf::enc::StreamFilter;
 use pathfinder_color::ColorU;
+use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
 use std::borrow::Cow;
 use std::path::Path;
 use std::sync::Arc;
@@ -104,7 +106,127 @@ fn resize_alpha(data: &[u8], src_width: u32, src_height: u32, dest_width: u32, d
     Some(dest.into_raw())
 }
 
+/// largest number of halvings
+const MAX_LEVEL: u32 = 8;
+
+/// Size in device pixels of an image drawn with `transform`.
+pub fn device_size(transform: Transform2F) -> (u32, u32) {
+    let x = Vector2F::new(transform.m11(), transform.m21()).length();
+    let y = Vector2F::new(transform.m12(), transform.m22()).length();
+    (x.ceil() as u32, y.ceil() as u32)
+}
+
+/// How often an image of `width` × `height` pixels can be halved and still have at least `target` pixels.
+pub fn scale_level(width: u32, height: u32, target: (u32, u32)) -> u32 {
+    let (target_width, target_height) = (target.0.max(1), target.1.max(1));
+    let mut level = 0;
+    while level < MAX_LEVEL && width >> (level + 1) >= target_width && height >> (level + 1) >= target_height {
+        level += 1;
+    }
+    level
+}
+
 pub fn load_image(image: &ImageXObject, resources: &Resources, resolve: &impl Resolve) -> Result<ImageData<'static>, PdfError> {
+    decode_image(image, resources, resolve)
+}
+
+/// Decode `image` at a reduced resolution if `target` (in pixels) is smaller than the image.
+///
+/// The result is at least as large as `target`, or the full image.
+pub fn load_image_scaled(image: &ImageXObject, resources: &Resources, resolve: &impl Resolve, target: (u32, u32)) -> Result<ImageData<'static>, PdfError> {
+    let level = scale_level(image.width as u32, image.height as u32, target);
+    load_image_level(image, resources, resolve, level)
+}
+
+/// Decode `image` halved `level` times.
+pub(crate) fn load_image_level(image: &ImageXObject, resources: &Resources, resolve: &impl Resolve, level: u32) -> Result<ImageData<'static>, PdfError> {
+    if level == 0 {
+        return decode_image(image, resources, resolve);
+    }
+    let factor = 1 << level;
+    if let Some(data) = decode_dct_scaled(image, resolve, factor)? {
+        return Ok(data);
+    }
+    Ok(downsample(decode_image(image, resources, resolve)?, factor))
+}
+
+/// Let the JPEG decoder skip the detail we don't need.
+/// Only for plain gray and RGB images without a soft mask, `None` otherwise.
+fn decode_dct_scaled(image: &ImageXObject, resolve: &impl Resolve, factor: u32) -> Result<Option<ImageData<'static>>, PdfError> {
+    use image::{ImageDecoder, ColorType, codecs::jpeg::JpegDecoder};
+
+    if image.smask.is_some() || !matches!(image.color_space, Some(ColorSpace::DeviceGray | ColorSpace::DeviceRGB | ColorSpace::Icc(_))) {
+        return Ok(None);
+    }
+    let (data, filter) = image.raw_image_data(resolve)?;
+    if !matches!(filter, Some(StreamFilter::DCTDecode(_))) {
+        return Ok(None);
+    }
+    let width = (image.width as u32).div_ceil(factor);
+    let height = (image.height as u32).div_ceil(factor);
+
+    let mut decoder = match JpegDecoder::new(&*data) {
+        Ok(decoder) => decoder,
+        Err(e) => {
+            warn!("can't read JPEG header: {}", e);
+            return Ok(None);
+        }
+    };
+    if decoder.scale(width.min(u16::MAX as u32) as u16, height.min(u16::MAX as u32) as u16).is_err() {
+        return Ok(None);
+    }
+    let (w, h) = decoder.dimensions();
+    let color_type = decoder.color_type();
+    let mut buf = vec![0; decoder.total_bytes() as usize];
+    if let Err(e) = decoder.read_image(&mut buf) {
+        warn!("can't decode JPEG: {}", e);
+        return Ok(None);
+    }
+    let pixels: Vec<ColorU> = match color_type {
+        ColorType::L8 => buf.iter().map(|&g| ColorU::new(g, g, g, 255)).collect(),
+        ColorType::Rgb8 => buf.chunks_exact(3).map(|c| ColorU::new(c[0], c[1], c[2], 255)).collect(),
+        _ => return Ok(None)
+    };
+    let data = match ImageData::new(pixels, w, h) {
+        Some(data) => data,
+        None => return Ok(None)
+    };
+    // the decoder can only scale down to 1/8
+    Ok(Some(downsample(data, (w / width).max(1))))
+}
+
+/// Shrink `image` by `factor` in both directions, averaging each block of pixels.
+fn downsample(image: ImageData<'static>, factor: u32) -> ImageData<'static> {
+    if factor <= 1 {
+        return image;
+    }
+    let f = factor as usize;
+    let (w, h) = (image.width as usize, image.height as usize);
+    let (dw, dh) = (w.div_ceil(f), h.div_ceil(f));
+    let mut data = Vec::with_capacity(dw * dh);
+    for y in 0 .. dh {
+        let rows = y * f .. ((y + 1) * f).min(h);
+        for x in 0 .. dw {
+            let cols = x * f .. ((x + 1) * f).min(w);
+            let mut sum = [0u32; 4];
+            let mut n = 0;
+            for row in rows.clone() {
+                for c in &image.data[row * w + cols.start .. row * w + cols.end] {
+                    sum[0] += c.r as u32;
+                    sum[1] += c.g as u32;
+                    sum[2] += c.b as u32;
+                    sum[3] += c.a as u32;
+                    n += 1;
+                }
+            }
+            let [r, g, b, a] = sum.map(|s| ((s + n / 2) / n) as u8);
+            data.push(ColorU::new(r, g, b, a));
+        }
+    }
+    ImageData::new(data, dw as u32, dh as u32).unwrap()
+}
+
+fn decode_image(image: &ImageXObject, resources: &Resources, resolve: &impl Resolve) -> Result<ImageData<'static>, PdfError> {
     let raw_data = image.image_data(resolve)?;
 
     let pixel_count = image.width as usize * image.height as usize;
@@ -180,7 +302,7 @@ pub fn load_image(image: &ImageXObject, resources: &Resources, resolve: &impl Re
     let cs = image.color_space.as_ref().and_then(|cs| resolve_cs(cs, &resources));
     let alpha = alpha.iter().cloned().chain(std::iter::repeat(255));
     let data_ratio = (raw_data.len() * 8) / pixel_count;
-    dbg!(data_ratio);
+    debug!("data ratio {}", data_ratio);
 
     let data = match data_ratio {
         1 | 2 | 4 | 8 => {
@@ -192,14 +314,14 @@ pub fn load_image(image: &ImageXObject, resources: &Resources, resolve: &impl Re
                 n => return Err(PdfError::Other { msg: format!("invalid bits per component {}", n)})
             };
             let pixel_data: &[u8] = &*pixel_data;
-            dbg!(&cs);
+            debug!("color space {:?}", cs);
             match cs {
                 Some(&ColorSpace::DeviceGray) => {
                     assert_eq!(pixel_data.len(), pixel_count);
                     pixel_data.iter().zip(alpha).map(|(&g, a)| ColorU { r: g, g: g, b: g, a }).collect()
                 }
                 Some(&ColorSpace::Indexed(ref base, hival, ref lookup)) => {
-                    match dbg!(resolve_cs(&**base, resources)) {
+                    match resolve_cs(&**base, resources) {
                         Some(ColorSpace::DeviceRGB) => {
                             let mut data = Vec::with_capacity(pixel_data.len());
                             for (&b, a) in pixel_data.iter().zip(alpha) {
@@ -310,3 +432,52 @@ fn cmyk2color_arr(data: &[u8], alpha: impl Iterator<Item=u8>) -> Vec<ColorU> {
     }).collect()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdf::object::XObject;
+    use crate::writer::{PdfBuilder, load_page};
+
+    #[test]
+    fn levels() {
+        assert_eq!(scale_level(8, 8, (2, 2)), 2);
+        assert_eq!(scale_level(8, 8, (3, 2)), 1);
+        assert_eq!(scale_level(8, 8, (16, 16)), 0);
+        assert_eq!(scale_level(1024, 1024, (1, 1)), MAX_LEVEL);
+        assert_eq!(device_size(Transform2F::from_scale(Vector2F::new(2.5, 3.0))), (3, 3));
+    }
+
+    #[test]
+    fn scaled_decoding() {
+        let load = |dict: &str, data: &[u8], target| {
+            let mut b = PdfBuilder::new();
+            let im = b.stream(&format!("/Type /XObject /Subtype /Image {}", dict), data);
+            b.resource(&format!("/XObject << /Im {} 0 R >>", im));
+            let (file, page) = load_page(b.finish(b""));
+            let resolver = file.resolver();
+            let resources = page.resources().unwrap();
+            let xobject = resolver.get(*resources.xobjects.values().next().unwrap()).unwrap();
+            match *xobject {
+                XObject::Image(ref im) => load_image_scaled(im, resources, &resolver, target).unwrap(),
+                _ => panic!("not an image")
+            }
+        };
+
+        // sizes that are not a multiple of the factor keep the partial blocks
+        let gray: Vec<u8> = (0 .. 15).map(|i| (i % 5) as u8 * 50).collect();
+        let image = load("/Width 5 /Height 3 /ColorSpace /DeviceGray /BitsPerComponent 8", &gray, (2, 1));
+        assert_eq!((image.width(), image.height()), (3, 2));
+        let row = [25, 125, 200].map(|g| ColorU::new(g, g, g, 255));
+        assert_eq!(image.data(), [row, row].concat());
+
+        // JPEGs are decoded at 1/8 by the decoder and further downsampled
+        let mut jpeg = vec![];
+        ::image::codecs::jpeg::JpegEncoder::new(&mut jpeg).encode(&[128; 64 * 64], 64, 64, ::image::ColorType::L8).unwrap();
+        let dict = "/Width 64 /Height 64 /ColorSpace /DeviceGray /BitsPerComponent 8 /Filter /DCTDecode";
+        for (target, size) in [((8, 8), 8), ((4, 4), 4), ((64, 64), 64)] {
+            let image = load(dict, &jpeg, target);
+            assert_eq!((image.width(), image.height()), (size, size));
+            assert!(image.data().iter().all(|c| (c.r as i32 - 128).abs() <= 2 && c.a == 255));
+        }
+    }
+}
@@ -23,24 +23,65 @@ mod renderstate;
 mod textstate;
 mod backend;
 pub mod tracer;
+pub mod raster;
 mod image;
 mod scene;
 mod font;
+mod provider;
+#[cfg(feature = "embed-fonts")]
+mod builtin;
+mod annot;
+mod appearance;
+mod optional_content;
+mod structure;
+mod options;
+mod diagnostics;
+mod report;
+mod glyphcache;
+mod lru;
+mod batch;
+mod error;
+mod progressive;
+mod tile;
+mod thumbnail;
+/// the PDF writer of the visual tests, for unit tests that need a document
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../tests/visual/writer.rs"]
+mod writer;
 
-pub use cache::{Cache};
-pub use fontentry::{FontEntry, TextEncoding};
-pub use backend::{DrawMode, Backend, BlendMode};
+pub use cache::{Cache, CacheBuilder, CacheLimits, ResourceStats};
+pub use lru::CacheStats;
+pub use font::{DumpFonts, LogSink};
+pub use provider::{FontProvider, FontQuery, FontArc, Substitute, DirectoryProvider, SystemFontProvider, SystemFont};
+pub use fontentry::{FontEntry, TextEncoding, Substitution};
+pub use backend::{DrawMode, Backend, BlendMode, Sampling};
 pub use scene::SceneBackend;
-pub use crate::image::{load_image, ImageData};
+pub use raster::RasterBackend;
+pub use crate::image::{load_image, load_image_scaled, device_size, scale_level, ImageData};
+pub use annot::{RenderIntent, render_annotations, annotation_visible};
+pub use options::{RenderOptions, CancelToken, CullStats, CullCount};
+pub use error::{RenderError, Budget};
+pub use progressive::{PreparedPage, PageRenderer};
+pub use tile::{render_tile, TileBackend, TileCache, TileKey};
+pub use thumbnail::thumbnail;
+pub use diagnostics::{Diagnostics, Diagnostic};
+pub use report::{FontReport, FontInfo, EncodingKind};
+pub use glyphcache::GlyphCache;
+pub use batch::{render_parallel, render_raster, ParallelOptions};
+pub use optional_content::OptionalContent;
+pub use structure::{StructTree, StructNode, StructElem, TaggedText, ExportFormat};
 use custom_debug_derive::Debug;
 
 use pdf::{object::*, content::TextMode};
 use pdf::error::PdfError;
+use pdf::primitive::Primitive;
 use pathfinder_geometry::{
     vector::{Vector2F},
     rect::RectF, transform2d::Transform2F,
 };
 use renderstate::RenderState;
+use options::RenderContext;
 use std::sync::Arc;
 use itertools::Itertools;
 const SCALE: f32 = 25.4 / 72.;
@@ -74,47 +115,153 @@ impl From<RectF> for BBox {
 }
 
 
-pub fn page_bounds(page: &Page) -> RectF {
-    let Rect { left, right, top, bottom } = page.media_box().expect("no media box");
-    RectF::from_points(Vector2F::new(left, bottom), Vector2F::new(right, top)) * SCALE
+/// Page boundary to render, PDF 32000-1:2008, 14.11.2.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum PageBox {
+    Media,
+    #[default]
+    Crop,
+    Bleed,
+    Trim,
+    Art,
 }
+
+fn rect_f(Rect { left, right, top, bottom }: Rect) -> RectF {
+    RectF::from_points(
+        Vector2F::new(left.min(right), bottom.min(top)),
+        Vector2F::new(left.max(right), bottom.max(top))
+    )
+}
+fn rect_from_primitive(p: &Primitive) -> Option<RectF> {
+    let arr = p.as_array().ok()?;
+    if arr.len() != 4 {
+        return None;
+    }
+    let n: Vec<f32> = arr.iter().map(|p| p.as_number()).collect::<Result<_, _>>().ok()?;
+    Some(rect_f(Rect { left: n[0], bottom: n[1], right: n[2], top: n[3] }))
+}
+
+/// The chosen page box in default user space.
+///
+/// MediaBox and CropBox are inherited from the page tree.
+/// The other boxes default to the CropBox. All boxes are clipped to the MediaBox.
+pub fn page_box_rect(page: &Page, page_box: PageBox) -> Result<RectF, PdfError> {
+    let media = rect_f(page.media_box()?);
+    let crop = || page.crop_box().map(rect_f).unwrap_or(media);
+    let rect = match page_box {
+        PageBox::Media => return Ok(media),
+        PageBox::Crop => crop(),
+        PageBox::Bleed => page.other.get("BleedBox").and_then(rect_from_primitive).unwrap_or_else(crop),
+        PageBox::Trim => page.trim_box.map(rect_f).unwrap_or_else(crop),
+        PageBox::Art => page.other.get("ArtBox").and_then(rect_from_primitive).unwrap_or_else(crop),
+    };
+    rect.intersection(media).ok_or_else(|| PdfError::Other { msg: format!("{:?} box does not intersect the MediaBox", page_box) })
+}
+/// The chosen page box in millimeters.
+pub fn page_bounds(page: &Page, page_box: PageBox) -> Result<RectF, PdfError> {
+    Ok(page_box_rect(page, page_box)? * SCALE)
+}
+/// Render with the default `RenderOptions`.
+///
+/// Optional content is ignored, so layers that the document hides by default are drawn as well.
+/// Use `render_page_with` and `OptionalContent::load` to hide them.
 pub fn render_page(backend: &mut impl Backend, resolve: &impl Resolve, page: &Page, transform: Transform2F) -> Result<Transform2F, PdfError> {
-    let bounds = page_bounds(page);
-    let rotate = Transform2F::from_rotation(page.rotate as f32 * std::f32::consts::PI / 180.);
-    let br = rotate * RectF::new(Vector2F::zero(), bounds.size());
-    let translate = Transform2F::from_translation(Vector2F::new(
-        -br.min_x().min(br.max_x()),
-        -br.min_y().min(br.max_y()),
-    ));
-    let view_box = transform * translate * br;
-    backend.set_view_box(view_box);
-    
-    let root_transformation = transform
-        * translate
-        * rotate
-        * Transform2F::row_major(SCALE, 0.0, -bounds.min_x(), 0.0, -SCALE, bounds.max_y());
-    
-    let resources = t!(page.resources());
+    Ok(render_page_with(backend, resolve, page, transform, &RenderOptions::default())?.transform)
+}
+
+pub struct Rendered {
+    /// maps PDF user space to device space, like the result of `render_page`
+    pub transform: Transform2F,
+    /// operators skipped in lenient mode
+    pub diagnostics: Diagnostics,
+    /// draw calls passed to the backend and skipped outside of the clip path or viewport
+    pub culling: CullStats,
+}
 
-    let contents = try_opt!(page.contents.as_ref());
-    let ops = contents.operations(resolve)?;
-    let mut renderstate = RenderState::new(backend, resolve, &resources, root_transformation);
-    for (i, op) in ops.iter().enumerate() {
-        debug!("op {}: {:?}", i, op);
-        renderstate.draw_op(op, i)?;
+/// Render the page contents followed by the visible annotations, clipped to the page box.
+///
+/// When cancelled or over budget, the backend keeps what was drawn until then.
+pub fn render_page_with(backend: &mut impl Backend, resolve: &impl Resolve, page: &Page, transform: Transform2F, options: &RenderOptions) -> Result<Rendered, RenderError> {
+    let context = RenderContext::new(options);
+    match draw_page(backend, resolve, page, transform, &context) {
+        Ok(transform) => Ok(Rendered {
+            transform,
+            culling: context.culling(),
+            diagnostics: context.into_diagnostics(),
+        }),
+        Err(e) => Err(context.error(e))
     }
+}
+/// Page box in user space, view box and the transformation from user space to device space.
+pub(crate) struct PageGeometry {
+    pub clip: RectF,
+    pub view_box: RectF,
+    pub root_transformation: Transform2F,
+}
+impl PageGeometry {
+    pub fn new(page: &Page, transform: Transform2F, page_box: PageBox) -> Result<Self, PdfError> {
+        let clip = page_box_rect(page, page_box)?;
+        let bounds = clip * SCALE;
+        let rotate = Transform2F::from_rotation(page.rotate as f32 * std::f32::consts::PI / 180.);
+        let br = rotate * RectF::new(Vector2F::zero(), bounds.size());
+        let translate = Transform2F::from_translation(Vector2F::new(
+            -br.min_x().min(br.max_x()),
+            -br.min_y().min(br.max_y()),
+        ));
+        let view_box = transform * translate * br;
+        let root_transformation = transform
+            * translate
+            * rotate
+            * Transform2F::row_major(SCALE, 0.0, -bounds.min_x(), 0.0, -SCALE, bounds.max_y());
+        Ok(PageGeometry { clip, view_box, root_transformation })
+    }
+    /// set the view box and paint the background
+    pub fn begin(&self, backend: &mut impl Backend, options: &RenderOptions) {
+        backend.set_view_box(self.view_box);
+        if let Some(color) = options.background {
+            backend.fill_background(self.view_box, color);
+        }
+    }
+}
+fn draw_page(backend: &mut impl Backend, resolve: &impl Resolve, page: &Page, transform: Transform2F, context: &RenderContext) -> Result<Transform2F, PdfError> {
+    let options = context.options;
+    let geometry = PageGeometry::new(page, transform, options.page_box)?;
+    geometry.begin(backend, options);
+    let PageGeometry { clip, root_transformation, .. } = geometry;
+    let resources = t!(page.resources());
 
+    if let Some(ref contents) = page.contents {
+        let ops = contents.operations(resolve)?;
+        let mut renderstate = RenderState::new(backend, resolve, &resources, root_transformation, context);
+        renderstate.clip_rect(clip);
+        let result = ops.iter().enumerate().try_for_each(|(i, op)| {
+            debug!("op {}: {:?}", i, op);
+            renderstate.draw_op(op, i)
+        });
+        renderstate.close_marked_content();
+        result?;
+    }
+    if options.annotations {
+        annot::draw_annotations(backend, resolve, page, root_transformation, context)?;
+    }
     Ok(root_transformation)
 }
 pub fn render_pattern(backend: &mut impl Backend, pattern: &Pattern, resolve: &impl Resolve) -> Result<(), PdfError> {
+    Ok(render_pattern_with(backend, pattern, resolve, &RenderOptions::default())?)
+}
+/// Render a tiling pattern cell with the budgets and cancellation of `options`.
+pub fn render_pattern_with(backend: &mut impl Backend, pattern: &Pattern, resolve: &impl Resolve, options: &RenderOptions) -> Result<(), RenderError> {
     match pattern {
         Pattern::Stream(ref dict, ref ops) => {
             let resources = resolve.get(dict.resources)?;
-            let mut renderstate = RenderState::new(backend, resolve, &*resources, Transform2F::default());
-            for (i, op) in ops.iter().enumerate() {
+            let context = RenderContext::new(options);
+            let mut renderstate = RenderState::new(backend, resolve, &*resources, Transform2F::default(), &context);
+            let result = ops.iter().enumerate().try_for_each(|(i, op)| {
                 debug!("op {}: {:?}", i, op);
-                renderstate.draw_op(op, i)?;
-            }
+                renderstate.draw_op(op, i)
+            });
+            renderstate.close_marked_content();
+            result.map_err(|e| context.error(e))?;
         }
         Pattern::Dict(_) => {}
     }
@@ -153,6 +300,9 @@ pub struct TextSpan {
     // apply this transform to a text draw in at the origin with the given width and font-size
     pub transform: Transform2F,
     pub mode: TextMode,
+
+    // marked content identifier of the enclosing marked content sequence, links to the structure tree
+    pub mcid: Option<i32>,
 }
 impl TextSpan {
     pub fn parts(&self) -> impl Iterator<Item=Part> + '_ {
@@ -0,0 +1,183 @@
+use std::collections::{HashMap, BTreeMap};
+use std::hash::Hash;
+use std::sync::Mutex;
+
+/// Usage of one cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// entries dropped to stay within the budget
+    pub evictions: u64,
+    pub entries: usize,
+    /// estimated size of all entries
+    pub bytes: usize,
+    pub budget: usize,
+}
+
+struct Entry<V> {
+    value: V,
+    size: usize,
+    last_used: u64,
+}
+
+struct Inner<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    // keys by last use, oldest first
+    order: BTreeMap<u64, K>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+/// A thread safe cache that drops the least recently used entries when the size of all entries exceeds the budget.
+pub struct LruCache<K, V> {
+    inner: Mutex<Inner<K, V>>,
+    size: fn(&V) -> usize,
+}
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    pub fn new(budget: usize, size: fn(&V) -> usize) -> Self {
+        LruCache {
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                clock: 0,
+                stats: CacheStats { budget, ..CacheStats::default() },
+            }),
+            size,
+        }
+    }
+    /// The cached value for `key`, or the result of `load`.
+    ///
+    /// `load` runs without holding the lock, so it can use the cache as well.
+    pub fn get(&self, key: K, load: impl FnOnce() -> V) -> V {
+        match self.try_get(key, || Ok::<V, std::convert::Infallible>(load())) {
+            Ok(value) => value,
+            Err(e) => match e {}
+        }
+    }
+    /// Like `get`, but errors of `load` are returned and not cached.
+    pub fn try_get<E>(&self, key: K, load: impl FnOnce() -> Result<V, E>) -> Result<V, E> {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let inner = &mut *inner;
+            inner.clock += 1;
+            let clock = inner.clock;
+            if let Some(entry) = inner.entries.get_mut(&key) {
+                inner.order.remove(&entry.last_used);
+                inner.order.insert(clock, key);
+                entry.last_used = clock;
+                inner.stats.hits += 1;
+                return Ok(entry.value.clone());
+            }
+            inner.stats.misses += 1;
+        }
+        let value = load()?;
+        let size = (self.size)(&value);
+
+        let mut inner = self.inner.lock().unwrap();
+        if size > inner.stats.budget {
+            return Ok(value);
+        }
+        inner.clock += 1;
+        let last_used = inner.clock;
+        inner.order.insert(last_used, key.clone());
+        if let Some(old) = inner.entries.insert(key, Entry { value: value.clone(), size, last_used }) {
+            // loaded by another thread in the meantime
+            inner.order.remove(&old.last_used);
+            inner.stats.bytes -= old.size;
+        }
+        inner.stats.bytes += size;
+        inner.evict();
+        Ok(value)
+    }
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats { entries: inner.entries.len(), ..inner.stats }
+    }
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.order.clear();
+        inner.stats.bytes = 0;
+    }
+}
+impl<K: Hash + Eq, V> Inner<K, V> {
+    fn evict(&mut self) {
+        while self.stats.bytes > self.stats.budget {
+            let oldest = match self.order.pop_first() {
+                Some((_, key)) => key,
+                None => break
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.stats.bytes -= entry.size;
+                self.stats.evictions += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(budget: usize) -> LruCache<&'static str, usize> {
+        LruCache::new(budget, |&size| size)
+    }
+
+    #[test]
+    fn hits_and_misses() {
+        let cache = cache(10);
+        assert_eq!(cache.get("a", || 1), 1);
+        assert_eq!(cache.get("a", || unreachable!()), 1);
+        assert_eq!(cache.get("b", || 2), 2);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries, stats.bytes), (1, 2, 2, 3));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = cache(3);
+        cache.get("a", || 1);
+        cache.get("b", || 1);
+        cache.get("c", || 1);
+        cache.get("a", || unreachable!());
+        cache.get("d", || 1);
+        assert_eq!(cache.stats().evictions, 1);
+        // "b" was used least recently
+        cache.get("a", || unreachable!());
+        cache.get("c", || unreachable!());
+        cache.get("d", || unreachable!());
+        assert_eq!(cache.get("b", || 0), 0);
+        let stats = cache.stats();
+        assert_eq!((stats.evictions, stats.entries, stats.bytes), (2, 3, 3));
+    }
+
+    #[test]
+    fn larger_than_budget() {
+        let cache = cache(3);
+        cache.get("a", || 1);
+        assert_eq!(cache.get("big", || 4), 4);
+        let stats = cache.stats();
+        assert_eq!((stats.evictions, stats.entries, stats.bytes), (0, 1, 1));
+        assert_eq!(cache.get("big", || 5), 5);
+    }
+
+    #[test]
+    fn errors_are_not_cached() {
+        let cache = cache(3);
+        assert_eq!(cache.try_get("a", || Err(())), Err(()));
+        assert_eq!(cache.try_get("a", || Ok::<_, ()>(1)), Ok(1));
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[test]
+    fn clear() {
+        let cache = cache(3);
+        cache.get("a", || 1);
+        cache.clear();
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes), (0, 0));
+        assert_eq!(cache.get("a", || 2), 2);
+        assert_eq!(cache.stats().misses, 2);
+    }
+}
@@ -0,0 +1,193 @@
+//! Optional content (layers), PDF 32000-1:2008, 8.11.
+
+use std::collections::HashMap;
+use pdf::object::{Resolve, PlainRef, MaybeRef, Trailer};
+use pdf::primitive::{Primitive, Dictionary};
+use pdf::error::{PdfError, Result};
+
+/// Visibility of optional content groups (OCGs).
+///
+/// The initial state comes from the default configuration (`/D`) in `/OCProperties`.
+/// Individual groups can be switched on or off with `set_visible`; these overrides take
+/// precedence over the document defaults.
+#[derive(Debug, Clone, Default)]
+pub struct OptionalContent {
+    groups: Vec<PlainRef>,
+    names: HashMap<PlainRef, String>,
+    defaults: HashMap<PlainRef, bool>,
+    overrides: HashMap<PlainRef, bool>,
+}
+impl OptionalContent {
+    /// A configuration where all content is visible.
+    pub fn new() -> Self {
+        OptionalContent::default()
+    }
+
+    /// Read the default configuration from the `/OCProperties` entry of the document catalog,
+    /// like `OptionalContent::load(&file.trailer, &resolver)`.
+    pub fn load(trailer: &Trailer, resolve: &impl Resolve) -> Result<Self> {
+        let catalog = resolve.resolve(trailer.root.get_ref().get_inner())?.into_dictionary()?;
+        match catalog.get("OCProperties") {
+            Some(p) => OptionalContent::from_properties(p, resolve),
+            None => Ok(OptionalContent::new())
+        }
+    }
+
+    /// Read the default configuration from an `/OCProperties` dictionary.
+    pub fn from_properties(properties: &Primitive, resolve: &impl Resolve) -> Result<Self> {
+        let properties = properties.clone().resolve(resolve)?.into_dictionary()?;
+        let mut oc = OptionalContent::new();
+
+        if let Some(ocgs) = properties.get("OCGs") {
+            for p in ocgs.clone().resolve(resolve)?.into_array()? {
+                let r = match p {
+                    Primitive::Reference(r) => r,
+                    _ => continue
+                };
+                if let Ok(group) = resolve.resolve(r).and_then(|p| p.into_dictionary()) {
+                    if let Some(Ok(name)) = group.get("Name").map(|n| n.as_string()) {
+                        oc.names.insert(r, name.to_string_lossy());
+                    }
+                }
+                oc.groups.push(r);
+            }
+        }
+
+        if let Some(config) = properties.get("D") {
+            let config = config.clone().resolve(resolve)?.into_dictionary()?;
+            let base = match config.get("BaseState") {
+                Some(Primitive::Name(ref name)) => name.as_str() != "OFF",
+                _ => true,
+            };
+            for &r in oc.groups.iter() {
+                oc.defaults.insert(r, base);
+            }
+            for (key, state) in [("ON", true), ("OFF", false)] {
+                for r in refs(config.get(key), resolve) {
+                    oc.defaults.insert(r, state);
+                }
+            }
+        }
+        Ok(oc)
+    }
+
+    /// All groups listed in `/OCProperties` with their name and current visibility.
+    pub fn groups(&self) -> impl Iterator<Item=(PlainRef, Option<&str>, bool)> + '_ {
+        self.groups.iter().map(move |&r| (r, self.names.get(&r).map(|s| s.as_str()), self.is_group_visible(r)))
+    }
+
+    /// Find a group by its `/Name`.
+    pub fn group_by_name(&self, name: &str) -> Option<PlainRef> {
+        self.groups.iter().cloned().find(|r| self.names.get(r).map(|s| s.as_str()) == Some(name))
+    }
+
+    /// Override the visibility of a group.
+    pub fn set_visible(&mut self, group: PlainRef, visible: bool) {
+        self.overrides.insert(group, visible);
+    }
+
+    /// Remove the override for a group, restoring the document default.
+    pub fn reset(&mut self, group: PlainRef) {
+        self.overrides.remove(&group);
+    }
+
+    pub fn is_group_visible(&self, group: PlainRef) -> bool {
+        self.overrides.get(&group).or_else(|| self.defaults.get(&group)).cloned().unwrap_or(true)
+    }
+
+    /// Evaluate an optional content group or membership dictionary (`/OC` entry or `/OC` marked content properties).
+    ///
+    /// Anything that can't be evaluated is considered visible.
+    pub fn is_visible(&self, p: &Primitive, resolve: &impl Resolve) -> bool {
+        let (dict, own_ref) = match *p {
+            Primitive::Reference(r) => match resolve.resolve(r).and_then(|p| p.into_dictionary()) {
+                Ok(dict) => (dict, Some(r)),
+                Err(e) => {
+                    warn!("invalid optional content {:?}: {:?}", r, e);
+                    return true;
+                }
+            },
+            Primitive::Dictionary(ref dict) => (dict.clone(), None),
+            _ => return true
+        };
+        self.is_dict_visible(&dict, own_ref, resolve)
+    }
+
+    /// Like `is_visible`, for an entry of the `/Properties` resource dictionary.
+    pub fn is_properties_visible(&self, properties: &MaybeRef<Dictionary>, resolve: &impl Resolve) -> bool {
+        let own_ref = match *properties {
+            MaybeRef::Indirect(ref r) => Some(r.get_ref().get_inner()),
+            MaybeRef::Direct(_) => None,
+        };
+        self.is_dict_visible(properties, own_ref, resolve)
+    }
+
+    fn is_dict_visible(&self, dict: &Dictionary, own_ref: Option<PlainRef>, resolve: &impl Resolve) -> bool {
+        match dict.get("Type") {
+            Some(Primitive::Name(ref t)) if t.as_str() == "OCMD" => {}
+            // a group
+            _ => return match own_ref {
+                Some(r) => self.is_group_visible(r),
+                None => {
+                    warn!("optional content group is not an indirect object, it is always visible");
+                    true
+                }
+            }
+        }
+
+        if let Some(ve) = dict.get("VE") {
+            match self.eval_expression(ve, resolve, 0) {
+                Ok(visible) => return visible,
+                Err(e) => warn!("invalid visibility expression: {:?}", e)
+            }
+        }
+        let states: Vec<bool> = refs(dict.get("OCGs"), resolve).into_iter().map(|r| self.is_group_visible(r)).collect();
+        if states.is_empty() {
+            return true;
+        }
+        match dict.get("P") {
+            Some(Primitive::Name(ref p)) if p.as_str() == "AllOn" => states.iter().all(|&v| v),
+            Some(Primitive::Name(ref p)) if p.as_str() == "AnyOff" => states.iter().any(|&v| !v),
+            Some(Primitive::Name(ref p)) if p.as_str() == "AllOff" => states.iter().all(|&v| !v),
+            _ => states.iter().any(|&v| v),
+        }
+    }
+
+    fn eval_expression(&self, p: &Primitive, resolve: &impl Resolve, depth: usize) -> Result<bool> {
+        if depth > 32 {
+            return Err(PdfError::Other { msg: "visibility expression nested too deep".into() });
+        }
+        match *p {
+            Primitive::Reference(r) => {
+                let p = resolve.resolve(r)?;
+                match p {
+                    Primitive::Array(_) => self.eval_expression(&p, resolve, depth + 1),
+                    _ => Ok(self.is_group_visible(r))
+                }
+            }
+            Primitive::Array(ref arr) => {
+                let (op, args) = arr.split_first().ok_or(PdfError::Other { msg: "empty visibility expression".into() })?;
+                let mut values = args.iter().map(|a| self.eval_expression(a, resolve, depth + 1));
+                match &*op.as_name()? {
+                    "And" => values.try_fold(true, |acc, v| v.map(|v| acc && v)),
+                    "Or" => values.try_fold(false, |acc, v| v.map(|v| acc || v)),
+                    "Not" => values.next().unwrap_or(Ok(false)).map(|v| !v),
+                    op => Err(PdfError::Other { msg: format!("unknown visibility operator {}", op) })
+                }
+            }
+            ref p => Err(PdfError::UnexpectedPrimitive { expected: "Reference or Array", found: p.get_debug_name() })
+        }
+    }
+}
+
+/// a single reference or an array of references
+fn refs(p: Option<&Primitive>, resolve: &impl Resolve) -> Vec<PlainRef> {
+    match p {
+        Some(&Primitive::Reference(r)) => match resolve.resolve(r) {
+            Ok(Primitive::Array(arr)) => arr.iter().filter_map(|p| p.as_reference().ok()).collect(),
+            _ => vec![r]
+        },
+        Some(Primitive::Array(ref arr)) => arr.iter().filter_map(|p| p.as_reference().ok()).collect(),
+        _ => vec![]
+    }
+}
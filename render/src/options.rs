@@ -0,0 +1,240 @@
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use instant::Instant;
+use pathfinder_color::ColorU;
+use pathfinder_geometry::rect::RectF;
+use pdf::content::Op;
+use pdf::object::Resources;
+use pdf::error::{PdfError, Result};
+use crate::diagnostics::Diagnostics;
+use crate::error::{RenderError, Budget};
+use crate::{PageBox, RenderIntent, OptionalContent};
+use crate::backend::Sampling;
+
+/// Stops rendering when cancelled. Clones share the same state.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+    // tokens this one was combined with, see `or`
+    linked: Vec<Arc<AtomicBool>>,
+}
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed) || self.linked.iter().any(|f| f.load(Ordering::Relaxed))
+    }
+    /// A token that is cancelled when either `self` or `other` is. Cancelling it cancels `self`.
+    pub fn or(&self, other: &CancelToken) -> CancelToken {
+        let mut linked = self.linked.clone();
+        linked.push(other.flag.clone());
+        linked.extend(other.linked.iter().cloned());
+        CancelToken { flag: self.flag.clone(), linked }
+    }
+}
+
+/// Options for `render_page_with`.
+#[derive(Clone, Debug)]
+pub struct RenderOptions<'a> {
+    /// Painted below the page contents. `None` leaves the page transparent.
+    pub background: Option<ColorU>,
+    /// Draw annotation appearances.
+    pub annotations: bool,
+    /// Visibility of optional content groups. `None` shows all content, including layers the document hides by default;
+    /// pass `OptionalContent::load` to respect them.
+    pub optional_content: Option<&'a OptionalContent>,
+    /// Default resources of the interactive form (AcroForm `/DR`), for the fonts of synthesized FreeText annotations.
+    pub form_resources: Option<&'a Resources>,
+    pub page_box: PageBox,
+    pub intent: RenderIntent,
+    pub images: bool,
+    /// Sampling of all images. With `None` images are sampled smoothly if they ask for `/Interpolate`
+    /// or are drawn smaller than their size, and with nearest neighbour otherwise.
+    pub image_sampling: Option<Sampling>,
+    /// Draw text. Text is passed to `Backend::add_text` either way.
+    pub text: bool,
+    /// Fill and stroke paths.
+    pub vectors: bool,
+    /// Fail after this many operators, including those in forms and appearance streams.
+    pub max_ops: Option<usize>,
+    /// Fail when rendering takes longer.
+    pub max_time: Option<Duration>,
+    /// Fail when the images and paths drawn exceed this many bytes.
+    pub max_memory: Option<usize>,
+    /// Checked before every operator.
+    pub cancel: Option<CancelToken>,
+    /// Device space area that is needed. Paths, glyphs and images outside of it are not passed to the backend.
+    pub viewport: Option<RectF>,
+    /// Skip operators that fail instead of aborting the page. Skipped operators are reported in the diagnostics.
+    pub lenient: bool,
+}
+impl<'a> Default for RenderOptions<'a> {
+    fn default() -> Self {
+        RenderOptions {
+            background: Some(ColorU::white()),
+            annotations: true,
+            optional_content: None,
+            form_resources: None,
+            page_box: PageBox::default(),
+            intent: RenderIntent::default(),
+            images: true,
+            image_sampling: None,
+            text: true,
+            vectors: true,
+            max_ops: None,
+            max_time: None,
+            max_memory: None,
+            cancel: None,
+            viewport: None,
+            lenient: false,
+        }
+    }
+}
+
+/// Draw calls of one kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CullCount {
+    /// passed to the backend
+    pub drawn: usize,
+    /// skipped because they were outside of the clip path or viewport
+    pub culled: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub paths: CullCount,
+    pub glyphs: CullCount,
+    pub images: CullCount,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DrawKind {
+    Path,
+    Glyph,
+    Image,
+}
+
+/// State shared by all `RenderState`s of one page.
+pub struct RenderContext<'a> {
+    pub options: &'a RenderOptions<'a>,
+    ops: Cell<usize>,
+    memory: Cell<usize>,
+    start: Instant,
+    // why rendering was stopped
+    stop: Cell<Option<RenderError>>,
+    culling: Cell<CullStats>,
+    diagnostics: RefCell<Diagnostics>,
+}
+impl<'a> RenderContext<'a> {
+    pub fn new(options: &'a RenderOptions<'a>) -> Self {
+        RenderContext {
+            options,
+            ops: Cell::new(0),
+            memory: Cell::new(0),
+            start: Instant::now(),
+            stop: Cell::new(None),
+            culling: Cell::default(),
+            diagnostics: RefCell::default()
+        }
+    }
+    /// Count one operator and check cancellation and the budgets.
+    pub fn count_op(&self) -> Result<()> {
+        let ops = self.ops.get() + 1;
+        self.ops.set(ops);
+        if self.stopped() {
+            return Err(PdfError::Other { msg: "rendering stopped".into() });
+        }
+        let options = self.options;
+        let stop = if options.cancel.as_ref().map_or(false, |c| c.is_cancelled()) {
+            RenderError::Cancelled
+        } else if let Some(max) = options.max_ops.filter(|&max| ops > max) {
+            RenderError::BudgetExceeded(Budget::Ops(max))
+        } else if let Some(max) = options.max_memory.filter(|&max| self.memory.get() > max) {
+            RenderError::BudgetExceeded(Budget::Memory(max))
+        // the clock is slow on some platforms
+        } else if let Some(max) = options.max_time.filter(|&max| ops % 64 == 0 && self.start.elapsed() > max) {
+            RenderError::BudgetExceeded(Budget::Time(max))
+        } else {
+            return Ok(());
+        };
+        let msg = stop.to_string();
+        self.stop.set(Some(stop));
+        Err(PdfError::Other { msg })
+    }
+    /// count memory used by an image or path against `max_memory`, checked at the next operator
+    pub fn add_memory(&self, bytes: usize) {
+        self.memory.set(self.memory.get().saturating_add(bytes));
+    }
+    /// Whether `bounds` in device space intersects `clip` and the viewport. Counts the draw call as drawn or culled.
+    pub(crate) fn visible(&self, kind: DrawKind, bounds: RectF, clip: Option<RectF>) -> bool {
+        let visible = clip.map_or(true, |clip| clip.intersects(bounds))
+            && self.options.viewport.map_or(true, |viewport| viewport.intersects(bounds));
+        let mut stats = self.culling.get();
+        let count = match kind {
+            DrawKind::Path => &mut stats.paths,
+            DrawKind::Glyph => &mut stats.glyphs,
+            DrawKind::Image => &mut stats.images,
+        };
+        if visible {
+            count.drawn += 1;
+        } else {
+            count.culled += 1;
+        }
+        self.culling.set(stats);
+        visible
+    }
+    pub fn culling(&self) -> CullStats {
+        self.culling.get()
+    }
+    fn stopped(&self) -> bool {
+        let stop = self.stop.take();
+        let stopped = stop.is_some();
+        self.stop.set(stop);
+        stopped
+    }
+    /// the reason if rendering was stopped by cancellation or a budget, otherwise `error`
+    pub fn error(&self, error: PdfError) -> RenderError {
+        self.stop.take().unwrap_or(RenderError::Pdf(error))
+    }
+    /// Record a failed operator. Returns false if rendering has to stop.
+    pub fn skip_failed(&self, op_nr: usize, op: &Op, error: &PdfError) -> bool {
+        if !self.tolerate() {
+            return false;
+        }
+        warn!("skipping op {}: {}", op_nr, error);
+        self.diagnostics.borrow_mut().add(op_nr, op, error);
+        true
+    }
+    /// whether rendering continues after an error
+    pub fn tolerate(&self) -> bool {
+        self.options.lenient && !self.stopped()
+    }
+    pub fn into_diagnostics(self) -> Diagnostics {
+        self.diagnostics.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combined_cancel_tokens() {
+        let (a, b) = (CancelToken::new(), CancelToken::new());
+        let either = a.or(&b);
+        assert!(!either.is_cancelled());
+        b.cancel();
+        assert!(either.is_cancelled() && !a.is_cancelled());
+
+        let (c, d) = (CancelToken::new(), CancelToken::new());
+        let either = c.or(&d);
+        either.cancel();
+        assert!(c.is_cancelled() && !d.is_cancelled());
+    }
+}
@@ -0,0 +1,146 @@
+use pathfinder_geometry::transform2d::Transform2F;
+use pdf::content::Op;
+use pdf::object::{Page, Resolve, Resources};
+use crate::{Backend, PageGeometry, RenderOptions, RenderError, Rendered, annot};
+use crate::options::RenderContext;
+use crate::renderstate::RenderState;
+
+/// A page with its content stream parsed, ready to be rendered in steps by a `PageRenderer`.
+pub struct PreparedPage<'a, R: Resolve> {
+    resolve: &'a R,
+    page: &'a Page,
+    resources: &'a Resources,
+    ops: Vec<Op>,
+    geometry: PageGeometry,
+    context: RenderContext<'a>,
+    // a renderer was created, the page is not drawn again
+    started: bool,
+}
+impl<'a, R: Resolve> PreparedPage<'a, R> {
+    pub fn new(resolve: &'a R, page: &'a Page, transform: Transform2F, options: &'a RenderOptions<'a>) -> Result<Self, RenderError> {
+        let geometry = PageGeometry::new(page, transform, options.page_box)?;
+        let resources: &Resources = page.resources()?;
+        let ops = match page.contents {
+            Some(ref contents) => contents.operations(resolve)?,
+            None => vec![]
+        };
+        Ok(PreparedPage {
+            resolve,
+            page,
+            resources,
+            ops,
+            geometry,
+            context: RenderContext::new(options),
+            started: false,
+        })
+    }
+    /// number of operators in the content stream
+    pub fn num_ops(&self) -> usize {
+        self.ops.len()
+    }
+    /// Start rendering into `backend`. This sets the view box and paints the background.
+    ///
+    /// A page is rendered only once, the renderers after the first one are done right away.
+    pub fn renderer<'b, B: Backend>(&'b mut self, backend: &'b mut B) -> PageRenderer<'b, R, B> {
+        let started = std::mem::replace(&mut self.started, true);
+        if started {
+            warn!("the page was already rendered");
+        } else {
+            self.geometry.begin(backend, self.context.options);
+        }
+        let prepared: &'b Self = self;
+        let mut state = RenderState::new(backend, prepared.resolve, prepared.resources, prepared.geometry.root_transformation, &prepared.context);
+        if !started {
+            state.clip_rect(prepared.geometry.clip);
+        }
+        PageRenderer {
+            prepared,
+            state,
+            next: 0,
+            chunk_size: 1000,
+            done: started,
+        }
+    }
+    /// The result after the renderer is done, like the one of `render_page_with`.
+    pub fn finish(self) -> Rendered {
+        Rendered {
+            transform: self.geometry.root_transformation,
+            culling: self.context.culling(),
+            diagnostics: self.context.into_diagnostics(),
+        }
+    }
+}
+
+/// Renders a page a chunk of operators at a time, so the caller can show partial results in between.
+///
+/// ```ignore
+/// let mut prepared = PreparedPage::new(&resolver, &page, transform, &options)?;
+/// let mut renderer = prepared.renderer(&mut backend);
+/// while !renderer.step(500)? {
+///     show(renderer.backend());
+/// }
+/// ```
+/// As an iterator it renders `chunk_size` operators per item.
+pub struct PageRenderer<'b, R: Resolve, B: Backend> {
+    prepared: &'b PreparedPage<'b, R>,
+    state: RenderState<'b, R, B>,
+    next: usize,
+    chunk_size: usize,
+    done: bool,
+}
+impl<'b, R: Resolve, B: Backend> PageRenderer<'b, R, B> {
+    pub fn chunk_size(mut self, ops: usize) -> Self {
+        self.chunk_size = ops.max(1);
+        self
+    }
+    /// The backend with everything drawn so far.
+    pub fn backend(&mut self) -> &mut B {
+        self.state.backend()
+    }
+    /// operators drawn and total
+    pub fn progress(&self) -> (usize, usize) {
+        (self.next, self.prepared.ops.len())
+    }
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+    /// Draw up to `max_ops` operators, followed by the annotations after the last one.
+    /// Returns true when the page is complete.
+    pub fn step(&mut self, max_ops: usize) -> Result<bool, RenderError> {
+        if self.done {
+            return Ok(true);
+        }
+        let prepared = self.prepared;
+        let context = &prepared.context;
+        let end = (self.next + max_ops).min(prepared.ops.len());
+        for (i, op) in prepared.ops.iter().enumerate().take(end).skip(self.next) {
+            debug!("op {}: {:?}", i, op);
+            if let Err(e) = self.state.draw_op(op, i) {
+                self.done = true;
+                self.state.close_marked_content();
+                return Err(context.error(e));
+            }
+        }
+        self.next = end;
+        if end < prepared.ops.len() {
+            return Ok(false);
+        }
+        self.done = true;
+        self.state.close_marked_content();
+        if context.options.annotations {
+            let backend = self.state.backend();
+            annot::draw_annotations(backend, prepared.resolve, prepared.page, prepared.geometry.root_transformation, context)
+                .map_err(|e| context.error(e))?;
+        }
+        Ok(true)
+    }
+}
+impl<'b, R: Resolve, B: Backend> Iterator for PageRenderer<'b, R, B> {
+    type Item = Result<(), RenderError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        Some(self.step(self.chunk_size).map(|_| ()))
+    }
+}
@@ -0,0 +1,639 @@
+//! Sources for fonts that are not embedded in the PDF.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use once_cell::sync::OnceCell;
+use pdf::font::{Font as PdfFont, FontData, FontDescriptor};
+use pdf::error::{PdfError, Result};
+use std::hash::Hash;
+use globalcache::ValueSize;
+use crate::font::FontRc;
+use crate::lru::LruCache;
+
+pub type FontArc = Arc<dyn font::Font + Send + Sync + 'static>;
+
+// FontDescriptor flags, PDF 32000-1:2008, 9.8.2
+pub const FLAG_FIXED_PITCH: u32 = 1 << 0;
+pub const FLAG_SERIF: u32 = 1 << 1;
+pub const FLAG_SYMBOLIC: u32 = 1 << 2;
+pub const FLAG_ITALIC: u32 = 1 << 6;
+pub const FLAG_FORCE_BOLD: u32 = 1 << 18;
+
+/// What is known about a font that has to be substituted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontQuery {
+    /// `/BaseFont`
+    pub name: String,
+    /// `/FontFamily` of the descriptor, or the name without subset prefix and style
+    pub family: String,
+    /// 100 (thin) to 900 (black)
+    pub weight: u16,
+    pub italic: bool,
+    /// `/Flags` of the font descriptor, 0 if there is none
+    pub flags: u32,
+}
+impl FontQuery {
+    /// Everything from the font name, like `Arial,BoldItalic`.
+    pub fn new(name: &str) -> FontQuery {
+        let (family, bold, italic) = parse_name(name);
+        FontQuery {
+            name: name.into(),
+            family: family.into(),
+            weight: if bold { 700 } else { 400 },
+            italic,
+            flags: 0,
+        }
+    }
+    /// Use the name and the font descriptor of `font`.
+    pub fn from_font(font: &PdfFont) -> Option<FontQuery> {
+        let mut query = FontQuery::new(font.name.as_ref()?.as_str());
+        if let Some(desc) = descriptor(font) {
+            query.flags = desc.flags;
+            if let Some(family) = desc.font_family.as_ref() {
+                query.family = family.to_string_lossy();
+            }
+            if let Some(weight) = desc.font_weight {
+                query.weight = weight as u16;
+            }
+            if desc.flags & FLAG_FORCE_BOLD != 0 {
+                query.weight = query.weight.max(700);
+            }
+            query.italic |= desc.flags & FLAG_ITALIC != 0 || desc.italic_angle != 0.0;
+        }
+        Some(query)
+    }
+    pub fn is_bold(&self) -> bool {
+        self.weight >= 600
+    }
+    pub fn is_fixed_pitch(&self) -> bool {
+        self.flags & FLAG_FIXED_PITCH != 0
+    }
+    pub fn is_serif(&self) -> bool {
+        self.flags & FLAG_SERIF != 0
+    }
+    pub fn is_symbolic(&self) -> bool {
+        self.flags & FLAG_SYMBOLIC != 0
+    }
+}
+
+fn descriptor(font: &PdfFont) -> Option<&FontDescriptor> {
+    match font.data {
+        FontData::Type1(ref info) | FontData::TrueType(ref info) => info.font_descriptor.as_ref(),
+        FontData::CIDFontType0(ref cid) | FontData::CIDFontType2(ref cid) => Some(&cid.font_descriptor),
+        FontData::Type0(ref t0) => t0.descendant_fonts.first().and_then(|f| descriptor(f)),
+        _ => None
+    }
+}
+
+/// The name without a subset prefix like `ABCDEF+`.
+fn strip_subset(name: &str) -> &str {
+    match name.split_once('+') {
+        Some((tag, rest)) if tag.len() == 6 && tag.chars().all(|c| c.is_ascii_uppercase()) => rest,
+        _ => name
+    }
+}
+
+/// Split a font name like `ABCDEF+Arial,BoldItalic` or `Times-Bold` into family name, bold and italic.
+pub fn parse_name(name: &str) -> (&str, bool, bool) {
+    let name = strip_subset(name);
+    let (base, style) = match name.find(|c| c == ',' || c == '-') {
+        Some(i) => (&name[..i], &name[i + 1..]),
+        None => (name, "")
+    };
+    let bold = ["Bold", "Black", "Heavy", "Semibold", "Demi"].iter().any(|s| style.contains(s) || base.ends_with(s));
+    let italic = ["Italic", "Oblique"].iter().any(|s| style.contains(s) || base.ends_with(s));
+    (base, bold, italic)
+}
+
+/// A font returned by a `FontProvider` and its style.
+///
+/// If the query asks for bold or italic and the font is not, the style is synthesized.
+#[derive(Clone)]
+pub struct Substitute {
+    pub font: FontArc,
+    /// file or font name, shown in the font report
+    pub name: String,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// A source of fonts for non-embedded fonts.
+///
+/// Providers are asked in the order they were added to the `CacheBuilder`.
+pub trait FontProvider: Send + Sync {
+    /// A font for `query`, or `None` if nothing matches.
+    fn get_font(&self, query: &FontQuery) -> Option<Substitute>;
+}
+
+/// Byte budget of the parsed fonts a provider keeps, unless set with `budget`.
+pub const PROVIDER_BUDGET: usize = 32 << 20;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Family {
+    Sans,
+    Serif,
+    Mono,
+}
+
+/// The family of a standard 14 font or one of the common metric-compatible names.
+pub(crate) fn standard_family(base: &str) -> Option<Family> {
+    match base {
+        "Helvetica" | "Arial" | "ArialMT" | "ArialNarrow" => Some(Family::Sans),
+        "Times" | "Times New Roman" | "TimesNewRoman" | "TimesNewRomanPS" | "TimesNewRomanPSMT" => Some(Family::Serif),
+        "Courier" | "CourierNew" | "CourierNewPS" | "CourierNewPSMT" | "Courier New" => Some(Family::Mono),
+        _ => None
+    }
+}
+
+/// Guess the family of an unknown font from the descriptor flags or else its name.
+pub(crate) fn guess_family(base: &str, query: &FontQuery) -> Family {
+    if query.is_fixed_pitch() {
+        return Family::Mono;
+    }
+    if query.is_serif() {
+        return Family::Serif;
+    }
+    let lower = base.to_ascii_lowercase();
+    if ["mono", "courier", "consol", "typewriter", "code"].iter().any(|s| lower.contains(s)) {
+        Family::Mono
+    } else if ["times", "serif", "roman", "georgia", "garamond", "cambria", "minion", "palatino", "book"].iter().any(|s| lower.contains(s))
+        && !lower.contains("sans") {
+        Family::Serif
+    } else {
+        Family::Sans
+    }
+}
+
+/// parsed fonts of a provider
+pub(crate) fn font_file_cache<K: Hash + Eq + Clone>(budget: usize) -> LruCache<K, Option<FontRc>> {
+    LruCache::new(budget, |font: &Option<FontRc>| font.as_ref().map(ValueSize::size).unwrap_or(1))
+}
+
+fn parse_file(path: &Path) -> Option<FontRc> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("can't open {} {:?}", path.display(), e);
+            return None;
+        }
+    };
+    match font::parse(&data) {
+        Ok(f) => Some(f.into()),
+        Err(e) => {
+            warn!("Font Error in {}: {:?}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// A directory with a `fonts.json` that maps font names to file names.
+pub struct DirectoryProvider {
+    dir: PathBuf,
+    fonts: HashMap<String, String>,
+    cache: LruCache<String, Option<FontRc>>,
+}
+impl DirectoryProvider {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        let path = dir.join("fonts.json");
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| PdfError::Other { msg: format!("can't read {}: {}", path.display(), e) })?;
+        let fonts = serde_json::from_str(&data)
+            .map_err(|e| PdfError::Other { msg: format!("{} is invalid: {}", path.display(), e) })?;
+        Ok(DirectoryProvider { dir, fonts, cache: font_file_cache(PROVIDER_BUDGET) })
+    }
+    /// Keep at most `bytes` of parsed fonts.
+    pub fn budget(mut self, bytes: usize) -> Self {
+        self.cache = font_file_cache(bytes);
+        self
+    }
+    fn load(&self, name: &str) -> Option<(FontArc, String)> {
+        let file_name = self.fonts.get(name)?;
+        let font = self.cache.get(file_name.clone(), || parse_file(&self.dir.join(file_name)))?.0;
+        Some((font, file_name.clone()))
+    }
+    /// the font mapped to "Arial"
+    pub fn fallback(&self) -> Option<Substitute> {
+        self.load("Arial").map(|(font, name)| Substitute { font, name, bold: false, italic: false })
+    }
+    /// A mapped font of the same family as `query` (by name or descriptor flags), used when no provider has a match.
+    /// Without one, this is `fallback`.
+    pub fn fallback_for(&self, query: &FontQuery) -> Option<Substitute> {
+        let (base, _, _) = parse_name(&query.name);
+        let names: &[&str] = if query.is_symbolic() && base.to_ascii_lowercase().contains("symbol") {
+            &["Symbol"]
+        } else {
+            match standard_family(base).or_else(|| standard_family(&query.family)).unwrap_or_else(|| guess_family(base, query)) {
+                Family::Sans => &["Helvetica"],
+                Family::Serif => &["Times-Roman", "Times New Roman"],
+                Family::Mono => &["Courier", "Courier New"],
+            }
+        };
+        names.iter().find_map(|name| self.load(name))
+            .map(|(font, name)| Substitute { font, name, bold: false, italic: false })
+            .or_else(|| self.fallback())
+    }
+}
+impl FontProvider for DirectoryProvider {
+    /// The mapping is by exact name, so the style is assumed to match.
+    fn get_font(&self, query: &FontQuery) -> Option<Substitute> {
+        self.load(&query.name).map(|(font, name)| Substitute { font, name, bold: query.is_bold(), italic: query.italic })
+    }
+}
+
+/// A font file found by `SystemFontProvider`.
+#[derive(Debug, Clone)]
+pub struct SystemFont {
+    pub path: PathBuf,
+    pub postscript_name: String,
+    pub family: String,
+    pub weight: u16,
+    pub italic: bool,
+    pub monospace: bool,
+}
+
+/// Fonts installed on the system, found by scanning the usual font directories.
+///
+/// Fonts are matched by PostScript name first and otherwise by family, weight and style.
+/// Only TrueType and OpenType files are considered.
+pub struct SystemFontProvider {
+    dirs: Vec<PathBuf>,
+    index: OnceCell<Vec<SystemFont>>,
+    cache: LruCache<PathBuf, Option<FontRc>>,
+}
+impl SystemFontProvider {
+    /// The platform font directories. They are scanned on first use.
+    pub fn new() -> Self {
+        SystemFontProvider::with_dirs(system_font_dirs())
+    }
+    pub fn with_dirs(dirs: Vec<PathBuf>) -> Self {
+        SystemFontProvider { dirs, index: OnceCell::new(), cache: font_file_cache(PROVIDER_BUDGET) }
+    }
+    /// Keep at most `bytes` of parsed fonts.
+    pub fn budget(mut self, bytes: usize) -> Self {
+        self.cache = font_file_cache(bytes);
+        self
+    }
+    pub fn fonts(&self) -> &[SystemFont] {
+        self.index.get_or_init(|| {
+            let mut fonts = vec![];
+            for dir in self.dirs.iter() {
+                scan_dir(dir, 0, &mut fonts);
+            }
+            debug!("found {} system fonts", fonts.len());
+            fonts
+        })
+    }
+    /// The best match for `query`, if any font has the same PostScript name or family.
+    pub fn find(&self, query: &FontQuery) -> Option<&SystemFont> {
+        let name = normalize(strip_subset(&query.name));
+        let family = normalize_family(&query.family);
+        let fonts = self.fonts();
+        if let Some(font) = fonts.iter().find(|f| normalize(&f.postscript_name) == name) {
+            return Some(font);
+        }
+        fonts.iter()
+            .filter(|f| normalize_family(&f.family) == family)
+            .min_by_key(|f| {
+                let weight = (f.weight as i32 - query.weight as i32).abs();
+                let italic = if f.italic != query.italic { 1000 } else { 0 };
+                let mono = if query.is_fixed_pitch() && !f.monospace { 500 } else { 0 };
+                weight + italic + mono
+            })
+    }
+}
+impl Default for SystemFontProvider {
+    fn default() -> Self {
+        SystemFontProvider::new()
+    }
+}
+impl FontProvider for SystemFontProvider {
+    fn get_font(&self, query: &FontQuery) -> Option<Substitute> {
+        let info = self.find(query)?;
+        debug!("using {} for {}", info.path.display(), query.name);
+        let font = self.cache.get(info.path.clone(), || parse_file(&info.path))?.0;
+        Some(Substitute { font, name: info.postscript_name.clone(), bold: info.weight >= 600, italic: info.italic })
+    }
+}
+
+fn system_font_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut dirs = vec![];
+    if cfg!(target_os = "windows") {
+        if let Some(windir) = std::env::var_os("WINDIR") {
+            dirs.push(PathBuf::from(windir).join("Fonts"));
+        }
+        if let Some(local) = std::env::var_os("LOCALAPPDATA") {
+            dirs.push(PathBuf::from(local).join("Microsoft").join("Windows").join("Fonts"));
+        }
+    } else if cfg!(target_os = "macos") {
+        dirs.push("/System/Library/Fonts".into());
+        dirs.push("/Library/Fonts".into());
+        if let Some(ref home) = home {
+            dirs.push(home.join("Library/Fonts"));
+        }
+    } else {
+        if let Some(data_home) = std::env::var_os("XDG_DATA_HOME") {
+            dirs.push(PathBuf::from(data_home).join("fonts"));
+        } else if let Some(ref home) = home {
+            dirs.push(home.join(".local/share/fonts"));
+        }
+        if let Some(ref home) = home {
+            dirs.push(home.join(".fonts"));
+        }
+        dirs.push("/usr/local/share/fonts".into());
+        dirs.push("/usr/share/fonts".into());
+    }
+    dirs
+}
+
+fn scan_dir(dir: &Path, depth: usize, fonts: &mut Vec<SystemFont>) {
+    if depth > 8 {
+        return;
+    }
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            scan_dir(&path, depth + 1, fonts);
+            continue;
+        }
+        let ext = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+        if !matches!(ext.as_deref(), Some("ttf") | Some("otf")) {
+            continue;
+        }
+        match read_font_info(&path) {
+            Ok(Some(font)) => fonts.push(font),
+            Ok(None) => {}
+            Err(e) => debug!("can't read {}: {}", path.display(), e)
+        }
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset .. offset + 2)?.try_into().ok()?))
+}
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset .. offset + 4)?.try_into().ok()?))
+}
+
+/// Read names and style from the `name`, `OS/2` and `post` tables.
+fn read_font_info(path: &Path) -> io::Result<Option<SystemFont>> {
+    let mut file = File::open(path)?;
+    let mut header = [0; 12];
+    file.read_exact(&mut header)?;
+    if !matches!(&header[.. 4], [0, 1, 0, 0] | b"OTTO" | b"true") {
+        return Ok(None);
+    }
+    let num_tables = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let mut records = vec![0; num_tables * 16];
+    file.read_exact(&mut records)?;
+
+    let mut table = |tag: &[u8; 4]| -> io::Result<Option<Vec<u8>>> {
+        let record = match records.chunks_exact(16).find(|r| &r[.. 4] == tag) {
+            Some(r) => r,
+            None => return Ok(None)
+        };
+        let offset = u32_at(record, 8).unwrap_or(0) as u64;
+        let length = (u32_at(record, 12).unwrap_or(0) as usize).min(1 << 20);
+        let mut data = vec![0; length];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut data)?;
+        Ok(Some(data))
+    };
+
+    let names = match table(b"name")? {
+        Some(data) => data,
+        None => return Ok(None)
+    };
+    let postscript_name = match name_entry(&names, 6) {
+        Some(name) => name,
+        None => return Ok(None)
+    };
+    // typographic family, if present, groups all weights
+    let family = name_entry(&names, 16).or_else(|| name_entry(&names, 1)).unwrap_or_else(|| postscript_name.clone());
+
+    let (mut weight, mut italic) = (400, false);
+    if let Some(os2) = table(b"OS/2")? {
+        weight = u16_at(&os2, 4).unwrap_or(400);
+        italic = u16_at(&os2, 62).map(|sel| sel & 1 != 0).unwrap_or(false);
+    }
+    let monospace = table(b"post")?.and_then(|post| u32_at(&post, 12)).map(|fixed| fixed != 0).unwrap_or(false);
+
+    Ok(Some(SystemFont {
+        path: path.into(),
+        postscript_name,
+        family,
+        weight,
+        italic,
+        monospace,
+    }))
+}
+
+/// A string from the `name` table, preferring Windows English names.
+fn name_entry(data: &[u8], name_id: u16) -> Option<String> {
+    let count = u16_at(data, 2)? as usize;
+    let storage = u16_at(data, 4)? as usize;
+    let mut best: Option<(u32, String)> = None;
+    for i in 0 .. count {
+        let r = 6 + 12 * i;
+        let (platform, language, id) = (u16_at(data, r)?, u16_at(data, r + 4)?, u16_at(data, r + 6)?);
+        if id != name_id {
+            continue;
+        }
+        let (length, offset) = (u16_at(data, r + 8)? as usize, u16_at(data, r + 10)? as usize);
+        let bytes = data.get(storage + offset .. storage + offset + length)?;
+        let (rank, s) = match platform {
+            0 | 3 => {
+                let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+                (if platform == 3 && language == 0x409 { 0 } else { 1 }, String::from_utf16_lossy(&units))
+            }
+            // Mac Roman, close enough for names
+            1 => (2, bytes.iter().map(|&b| b as char).collect()),
+            _ => continue
+        };
+        if best.as_ref().map(|&(r, _)| rank < r).unwrap_or(true) {
+            best = Some((rank, s));
+        }
+    }
+    best.map(|(_, s)| s)
+}
+
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect()
+}
+/// like `normalize`, without the suffixes of names like `ArialMT` or `TimesNewRomanPSMT`
+fn normalize_family(name: &str) -> String {
+    let mut name = normalize(name);
+    for suffix in ["psmt", "mt", "ps"] {
+        if name.len() > suffix.len() && name.ends_with(suffix) {
+            name.truncate(name.len() - suffix.len());
+            break;
+        }
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_parsing() {
+        assert_eq!(parse_name("Helvetica"), ("Helvetica", false, false));
+        assert_eq!(parse_name("ABCDEF+Arial,BoldItalic"), ("Arial", true, true));
+        assert_eq!(parse_name("Times-Bold"), ("Times", true, false));
+        assert_eq!(parse_name("Helvetica-Oblique"), ("Helvetica", false, true));
+        assert_eq!(parse_name("Arial-BoldMT"), ("Arial", true, false));
+        assert_eq!(parse_name("ArialBlack"), ("ArialBlack", true, false));
+        // only six uppercase letters are a subset tag
+        assert_eq!(parse_name("abcdef+Foo"), ("abcdef+Foo", false, false));
+        assert_eq!(parse_name("ABC+Foo"), ("ABC+Foo", false, false));
+    }
+
+    #[test]
+    fn query_from_name() {
+        let query = FontQuery::new("XYZABC+Georgia,Italic");
+        assert_eq!((query.family.as_str(), query.weight, query.italic), ("Georgia", 400, true));
+        assert!(!query.is_bold());
+        assert!(FontQuery::new("Georgia-Bold").is_bold());
+    }
+
+    #[test]
+    fn family_normalization() {
+        assert_eq!(normalize("Times New Roman"), "timesnewroman");
+        assert_eq!(normalize_family("ArialMT"), "arial");
+        assert_eq!(normalize_family("TimesNewRomanPSMT"), "timesnewroman");
+        assert_eq!(normalize_family("Courier New PS"), "couriernew");
+        assert_eq!(normalize_family("MT"), "mt");
+    }
+
+    fn system_font(postscript_name: &str, family: &str, weight: u16, italic: bool, monospace: bool) -> SystemFont {
+        SystemFont {
+            path: format!("{}.ttf", postscript_name).into(),
+            postscript_name: postscript_name.into(),
+            family: family.into(),
+            weight,
+            italic,
+            monospace,
+        }
+    }
+
+    fn provider(fonts: Vec<SystemFont>) -> SystemFontProvider {
+        let provider = SystemFontProvider::with_dirs(vec![]);
+        provider.index.set(fonts).unwrap();
+        provider
+    }
+
+    #[test]
+    fn system_font_matching() {
+        let provider = provider(vec![
+            system_font("ArialMT", "Arial", 400, false, false),
+            system_font("Arial-BoldMT", "Arial", 700, false, false),
+            system_font("Arial-ItalicMT", "Arial", 400, true, false),
+            system_font("Arial-BoldItalicMT", "Arial", 700, true, false),
+            system_font("Arial-BlackMT", "Arial", 900, false, false),
+            system_font("DejaVuSans", "DejaVu Sans", 400, false, false),
+            system_font("DejaVuSansMono", "DejaVu Sans", 400, false, true),
+        ]);
+        let find = |query: &FontQuery| provider.find(query).map(|f| f.postscript_name.as_str());
+
+        // by PostScript name, even with a subset prefix
+        assert_eq!(find(&FontQuery::new("ABCDEF+Arial-BoldMT")), Some("Arial-BoldMT"));
+        // by family and style
+        assert_eq!(find(&FontQuery::new("Arial,Bold")), Some("Arial-BoldMT"));
+        assert_eq!(find(&FontQuery::new("Arial,Italic")), Some("Arial-ItalicMT"));
+        assert_eq!(find(&FontQuery::new("ArialMT,BoldItalic")), Some("Arial-BoldItalicMT"));
+        assert_eq!(find(&FontQuery { weight: 850, ..FontQuery::new("Arial") }), Some("Arial-BlackMT"));
+        assert_eq!(find(&FontQuery { weight: 600, ..FontQuery::new("Arial") }), Some("Arial-BoldMT"));
+        // fixed pitch prefers the monospace face of the family
+        let mono = FontQuery { family: "DejaVu Sans".into(), flags: FLAG_FIXED_PITCH, ..FontQuery::new("Foo") };
+        assert_eq!(find(&mono), Some("DejaVuSansMono"));
+        assert_eq!(find(&FontQuery::new("Verdana")), None);
+    }
+
+    /// A name table with one record for each of `names` (platform, language, name id, text).
+    fn name_table(names: &[(u16, u16, u16, &str)]) -> Vec<u8> {
+        let mut records = vec![];
+        let mut storage = vec![];
+        for &(platform, language, id, text) in names {
+            let bytes: Vec<u8> = match platform {
+                1 => text.bytes().collect(),
+                _ => text.encode_utf16().flat_map(|u| u.to_be_bytes()).collect(),
+            };
+            let encoding = if platform == 3 { 1 } else { 0 };
+            for v in [platform, encoding, language, id, bytes.len() as u16, storage.len() as u16] {
+                records.extend_from_slice(&v.to_be_bytes());
+            }
+            storage.extend_from_slice(&bytes);
+        }
+        let mut data = vec![];
+        for v in [0, names.len() as u16, 6 + records.len() as u16] {
+            data.extend_from_slice(&v.to_be_bytes());
+        }
+        data.extend_from_slice(&records);
+        data.extend_from_slice(&storage);
+        data
+    }
+
+    #[test]
+    fn name_entries() {
+        let data = name_table(&[
+            (1, 0, 1, "Mac Family"),
+            (3, 0x407, 1, "German Family"),
+            (3, 0x409, 1, "Family"),
+            (3, 0x407, 6, "PSName"),
+        ]);
+        assert_eq!(name_entry(&data, 1).as_deref(), Some("Family"));
+        assert_eq!(name_entry(&data, 6).as_deref(), Some("PSName"));
+        assert_eq!(name_entry(&data, 16), None);
+        assert_eq!(name_entry(&data[.. 20], 1), None);
+    }
+
+    #[test]
+    fn font_info() {
+        let mut os2 = vec![0; 78];
+        os2[4 .. 6].copy_from_slice(&700u16.to_be_bytes());
+        os2[62 .. 64].copy_from_slice(&1u16.to_be_bytes());
+        let mut post = vec![0; 32];
+        post[12 .. 16].copy_from_slice(&1u32.to_be_bytes());
+        let tables: [(&[u8; 4], Vec<u8>); 3] = [
+            (b"OS/2", os2),
+            (b"name", name_table(&[(3, 0x409, 1, "Test Sans"), (3, 0x409, 16, "Test"), (3, 0x409, 6, "Test-BoldItalic")])),
+            (b"post", post),
+        ];
+
+        let mut file = vec![0, 1, 0, 0];
+        file.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+        file.extend_from_slice(&[0; 6]);
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, data) in tables.iter() {
+            file.extend_from_slice(&tag[..]);
+            file.extend_from_slice(&[0; 4]);
+            file.extend_from_slice(&(offset as u32).to_be_bytes());
+            file.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            offset += data.len();
+        }
+        for (_, data) in tables.iter() {
+            file.extend_from_slice(data);
+        }
+
+        let path = std::env::temp_dir().join(format!("pdf_render_font_info_{}.ttf", std::process::id()));
+        std::fs::write(&path, file).unwrap();
+        let info = read_font_info(&path);
+        std::fs::write(&path, b"not a font file").unwrap();
+        let not_a_font = read_font_info(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let info = info.unwrap().unwrap();
+        assert_eq!((info.postscript_name.as_str(), info.family.as_str()), ("Test-BoldItalic", "Test"));
+        assert_eq!((info.weight, info.italic, info.monospace), (700, true, true));
+        assert!(not_a_font.unwrap().is_none());
+    }
+}
@@ -0,0 +1,343 @@
+use pathfinder_color::ColorU;
+use pathfinder_content::{
+    fill::FillRule,
+    stroke::OutlineStrokeToFill,
+    outline::Outline,
+    dash::OutlineDash,
+    segment::SegmentKind,
+};
+use pathfinder_geometry::{
+    vector::Vector2F,
+    rect::RectF,
+    transform2d::Transform2F,
+};
+use pdf::object::{Ref, XObject, ImageXObject, Resolve, Resources, MaybeRef};
+use pdf::font::Font as PdfFont;
+use pdf::error::PdfError;
+use std::sync::Arc;
+
+use crate::{TextSpan, DrawMode, Backend, FontEntry, Fill, ImageData, load_image_scaled, device_size, backend::{BlendMode, Sampling}};
+use crate::cache::Cache;
+
+/// number of sub-scanlines sampled per pixel row
+const SUBSAMPLES: usize = 4;
+
+/// A software backend that rasterizes into an RGBA pixmap.
+///
+/// It does not need a GPU and is meant for headless use (tests, batch conversion).
+pub struct RasterBackend<'a> {
+    width: u32,
+    height: u32,
+    origin: Vector2F,
+    pixels: Vec<ColorU>,
+    clip: Option<Vec<f32>>,
+    cache: Option<&'a Cache>,
+    sampling: Sampling,
+}
+impl<'a> RasterBackend<'a> {
+    /// Without a cache, no fonts are loaded and text is not drawn.
+    pub fn new(cache: Option<&'a Cache>) -> Self {
+        RasterBackend {
+            width: 0,
+            height: 0,
+            origin: Vector2F::zero(),
+            pixels: vec![],
+            clip: None,
+            cache,
+            sampling: Sampling::Nearest,
+        }
+    }
+    pub fn finish(self) -> ImageData<'static> {
+        ImageData::new(self.pixels, self.width, self.height).unwrap()
+    }
+
+    fn fill_outline(&mut self, outline: &Outline, fill_rule: FillRule, fill: Fill, alpha: f32) {
+        let (r, g, b) = match fill {
+            Fill::Solid(r, g, b) => (r, g, b),
+            Fill::Pattern(_) => (0., 0., 0.),
+        };
+        let offset = Transform2F::from_translation(-self.origin);
+        let edges = flatten(&outline.clone().transformed(&offset));
+        let (width, height) = (self.width, self.height);
+        let pixels = &mut self.pixels;
+        let clip = self.clip.as_deref();
+        rasterize(&edges, fill_rule, width, height, |x, y, coverage| {
+            let idx = y * width as usize + x;
+            let a = coverage * alpha * clip.map(|c| c[idx]).unwrap_or(1.0);
+            if a > 0.0 {
+                pixels[idx] = blend(pixels[idx], (r, g, b), a);
+            }
+        });
+    }
+
+    fn draw_image_data(&mut self, image: &ImageData, transform: Transform2F, sampling: Sampling) {
+        let transform = Transform2F::from_translation(-self.origin) * transform;
+        let bounds = transform * RectF::new(Vector2F::zero(), Vector2F::new(1.0, 1.0));
+        let bounds = match bounds.intersection(RectF::new(Vector2F::zero(), Vector2F::new(self.width as f32, self.height as f32))) {
+            Some(r) => r.round_out(),
+            None => return
+        };
+        let inv = transform.inverse();
+        let (w, h) = (image.width() as usize, image.height() as usize);
+        let data = image.data();
+        for y in bounds.min_y() as usize .. bounds.max_y() as usize {
+            for x in bounds.min_x() as usize .. bounds.max_x() as usize {
+                let p = inv * Vector2F::new(x as f32 + 0.5, y as f32 + 0.5);
+                if p.x() < 0.0 || p.x() >= 1.0 || p.y() <= 0.0 || p.y() > 1.0 {
+                    continue;
+                }
+                let (u, v) = (p.x() * w as f32, (1.0 - p.y()) * h as f32);
+                let c = match sampling {
+                    Sampling::Nearest => data[(v as usize).min(h - 1) * w + (u as usize).min(w - 1)],
+                    Sampling::Smooth => bilinear(data, w, h, u - 0.5, v - 0.5),
+                };
+                let idx = y * self.width as usize + x;
+                let a = c.a as f32 / 255. * self.clip.as_ref().map(|c| c[idx]).unwrap_or(1.0);
+                if a > 0.0 {
+                    let rgb = (c.r as f32 / 255., c.g as f32 / 255., c.b as f32 / 255.);
+                    self.pixels[idx] = blend(self.pixels[idx], rgb, a);
+                }
+            }
+        }
+    }
+}
+impl<'a> Backend for RasterBackend<'a> {
+    fn set_clip_path(&mut self, path: Option<&Outline>) {
+        self.clip = path.map(|path| {
+            let offset = Transform2F::from_translation(-self.origin);
+            let edges = flatten(&path.clone().transformed(&offset));
+            let mut mask = vec![0.0; self.width as usize * self.height as usize];
+            let width = self.width;
+            rasterize(&edges, FillRule::Winding, self.width, self.height, |x, y, coverage| {
+                mask[y * width as usize + x] = coverage;
+            });
+            mask
+        });
+    }
+    fn set_view_box(&mut self, view_box: RectF) {
+        let size = view_box.size().ceil().to_i32();
+        self.width = size.x().max(0) as u32;
+        self.height = size.y().max(0) as u32;
+        self.origin = view_box.origin();
+        self.pixels = vec![ColorU::transparent_black(); self.width as usize * self.height as usize];
+        self.clip = None;
+    }
+    fn draw(&mut self, outline: &Outline, mode: &DrawMode, fill_rule: FillRule, transform: Transform2F) {
+        match *mode {
+            DrawMode::Fill(fill, alpha) | DrawMode::FillStroke(fill, alpha, _, _, _) => {
+                self.fill_outline(&outline.clone().transformed(&transform), fill_rule, fill, alpha);
+            }
+            _ => {}
+        }
+        match *mode {
+            DrawMode::Stroke(fill, alpha, ref style) | DrawMode::FillStroke(_, _, fill, alpha, ref style) => {
+                let contour = match style.dash_pattern {
+                    Some((ref pat, phase)) => {
+                        let dashed = OutlineDash::new(outline, &*pat, phase).into_outline();
+                        let mut stroke = OutlineStrokeToFill::new(&dashed, style.style);
+                        stroke.offset();
+                        stroke.into_outline()
+                    }
+                    None => {
+                        let mut stroke = OutlineStrokeToFill::new(outline, style.style);
+                        stroke.offset();
+                        stroke.into_outline()
+                    }
+                };
+                self.fill_outline(&contour.transformed(&transform), FillRule::Winding, fill, alpha);
+            }
+            _ => {}
+        }
+    }
+    fn draw_image(&mut self, xref: Ref<XObject>, im: &ImageXObject, resources: &Resources, transform: Transform2F, _mode: BlendMode, resolve: &impl Resolve) {
+        let size = device_size(transform);
+        match self.cache {
+            Some(cache) => match *cache.get_image_scaled(xref, im, resources, resolve, size).0 {
+                Ok(ref image) => {
+                    let (w, h) = (image.size().x() as u32, image.size().y() as u32);
+                    if let Some(data) = ImageData::new(&image.pixels()[..], w, h) {
+                        self.draw_image_data(&data, transform, self.sampling);
+                    }
+                }
+                Err(ref e) => warn!("failed to load image: {:?}", e),
+            }
+            None => match load_image_scaled(im, resources, resolve, size) {
+                Ok(image) => self.draw_image_data(&image, transform, self.sampling),
+                Err(e) => warn!("failed to load image: {:?}", e),
+            }
+        }
+    }
+    fn draw_inline_image(&mut self, im: &Arc<ImageXObject>, resources: &Resources, transform: Transform2F, _mode: BlendMode, resolve: &impl Resolve) {
+        // inline images have no reference to cache them by
+        match load_image_scaled(im, resources, resolve, device_size(transform)) {
+            Ok(image) => self.draw_image_data(&image, transform, self.sampling),
+            Err(e) => warn!("failed to load inline image: {:?}", e),
+        }
+    }
+    fn set_image_sampling(&mut self, sampling: Sampling) {
+        self.sampling = sampling;
+    }
+    fn get_font(&mut self, font_ref: &MaybeRef<PdfFont>, resolve: &impl Resolve) -> Result<Option<Arc<FontEntry>>, PdfError> {
+        match self.cache {
+            Some(cache) => cache.get_font(font_ref, resolve),
+            None => Ok(None)
+        }
+    }
+    fn add_text(&mut self, _span: TextSpan) {}
+}
+
+/// color at `(x, y)` in pixel coordinates, interpolated between the four nearest pixel centers
+fn bilinear(data: &[ColorU], w: usize, h: usize, x: f32, y: f32) -> ColorU {
+    let (x, y) = (x.clamp(0.0, (w - 1) as f32), y.clamp(0.0, (h - 1) as f32));
+    let (x0, y0) = (x as usize, y as usize);
+    let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let mix = |f: fn(ColorU) -> u8| {
+        let top = f(data[y0 * w + x0]) as f32 * (1.0 - fx) + f(data[y0 * w + x1]) as f32 * fx;
+        let bottom = f(data[y1 * w + x0]) as f32 * (1.0 - fx) + f(data[y1 * w + x1]) as f32 * fx;
+        (top * (1.0 - fy) + bottom * fy + 0.5) as u8
+    };
+    ColorU::new(mix(|c| c.r), mix(|c| c.g), mix(|c| c.b), mix(|c| c.a))
+}
+
+fn blend(dst: ColorU, (r, g, b): (f32, f32, f32), a: f32) -> ColorU {
+    let a = a.min(1.0);
+    let da = dst.a as f32 / 255.;
+    let out_a = a + da * (1.0 - a);
+    if out_a <= 0.0 {
+        return ColorU::transparent_black();
+    }
+    let mix = |s: f32, d: u8| {
+        let v = (s * a + d as f32 / 255. * da * (1.0 - a)) / out_a;
+        (v.max(0.0).min(1.0) * 255. + 0.5) as u8
+    };
+    ColorU::new(mix(r, dst.r), mix(g, dst.g), mix(b, dst.b), (out_a * 255. + 0.5) as u8)
+}
+
+/// Turn the outline into a list of line segments. All contours are treated as closed.
+fn flatten(outline: &Outline) -> Vec<(Vector2F, Vector2F)> {
+    let mut edges = vec![];
+    for contour in outline.contours() {
+        let mut points = vec![];
+        for segment in contour.iter(pathfinder_content::outline::ContourIterFlags::IGNORE_CLOSE_SEGMENT) {
+            let p0 = segment.baseline.from();
+            let p3 = segment.baseline.to();
+            if points.is_empty() {
+                points.push(p0);
+            }
+            match segment.kind {
+                SegmentKind::None => {}
+                SegmentKind::Line => points.push(p3),
+                SegmentKind::Quadratic => {
+                    let c = segment.ctrl.from();
+                    let n = steps(p0, c, c, p3);
+                    for i in 1 ..= n {
+                        let t = i as f32 / n as f32;
+                        let s = 1.0 - t;
+                        points.push(p0 * (s * s) + c * (2.0 * s * t) + p3 * (t * t));
+                    }
+                }
+                SegmentKind::Cubic => {
+                    let (c1, c2) = (segment.ctrl.from(), segment.ctrl.to());
+                    let n = steps(p0, c1, c2, p3);
+                    for i in 1 ..= n {
+                        let t = i as f32 / n as f32;
+                        let s = 1.0 - t;
+                        points.push(p0 * (s * s * s) + c1 * (3.0 * s * s * t) + c2 * (3.0 * s * t * t) + p3 * (t * t * t));
+                    }
+                }
+            }
+        }
+        if points.len() < 2 {
+            continue;
+        }
+        for (&a, &b) in points.iter().zip(points.iter().skip(1).chain(points.first())) {
+            if a.y() != b.y() {
+                edges.push((a, b));
+            }
+        }
+    }
+    edges
+}
+
+/// number of line segments to approximate a curve with, based on the length of the control polygon
+fn steps(p0: Vector2F, c1: Vector2F, c2: Vector2F, p3: Vector2F) -> usize {
+    let len = (c1 - p0).length() + (c2 - c1).length() + (p3 - c2).length();
+    (len / 2.0).ceil().max(1.0).min(256.0) as usize
+}
+
+/// Scanline rasterizer. Calls `paint(x, y, coverage)` for every pixel with non-zero coverage.
+fn rasterize(edges: &[(Vector2F, Vector2F)], fill_rule: FillRule, width: u32, height: u32, mut paint: impl FnMut(usize, usize, f32)) {
+    if edges.is_empty() || width == 0 || height == 0 {
+        return;
+    }
+    let (min_y, max_y) = edges.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &(a, b)| {
+        (lo.min(a.y()).min(b.y()), hi.max(a.y()).max(b.y()))
+    });
+    let y0 = (min_y.floor().max(0.0) as usize).min(height as usize);
+    let y1 = (max_y.ceil().max(0.0) as usize).min(height as usize);
+
+    let mut coverage = vec![0.0f32; width as usize];
+    let mut crossings: Vec<(f32, i32)> = vec![];
+    let weight = 1.0 / SUBSAMPLES as f32;
+
+    for y in y0 .. y1 {
+        coverage.iter_mut().for_each(|c| *c = 0.0);
+        let mut touched = false;
+        for sub in 0 .. SUBSAMPLES {
+            let sy = y as f32 + (sub as f32 + 0.5) * weight;
+            crossings.clear();
+            for &(a, b) in edges {
+                let (top, bottom, dir) = if a.y() < b.y() { (a, b, 1) } else { (b, a, -1) };
+                if sy < top.y() || sy >= bottom.y() {
+                    continue;
+                }
+                let t = (sy - top.y()) / (bottom.y() - top.y());
+                crossings.push((top.x() + t * (bottom.x() - top.x()), dir));
+            }
+            crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                let inside = match fill_rule {
+                    FillRule::Winding => winding != 0,
+                    FillRule::EvenOdd => winding % 2 != 0,
+                };
+                if inside {
+                    add_span(&mut coverage, pair[0].0, pair[1].0, weight);
+                    touched = true;
+                }
+            }
+        }
+        if touched {
+            for (x, &c) in coverage.iter().enumerate() {
+                if c > 0.0 {
+                    paint(x, y, c.min(1.0));
+                }
+            }
+        }
+    }
+}
+
+fn add_span(coverage: &mut [f32], x0: f32, x1: f32, weight: f32) {
+    let width = coverage.len() as f32;
+    let x0 = x0.max(0.0).min(width);
+    let x1 = x1.max(0.0).min(width);
+    if x1 <= x0 {
+        return;
+    }
+    let i0 = x0 as usize;
+    let i1 = x1 as usize;
+    if i0 == i1 {
+        coverage[i0] += (x1 - x0) * weight;
+        return;
+    }
+    coverage[i0] += (i0 as f32 + 1.0 - x0) * weight;
+    for c in &mut coverage[i0 + 1 .. i1] {
+        *c += weight;
+    }
+    if i1 < coverage.len() {
+        coverage[i1] += (x1 - i1 as f32) * weight;
+    }
+}
@@ -1,9 +1,12 @@
 use pdf::object::*;
 use pdf::primitive::{Primitive, Dictionary};
-use pdf::content::{Op, Matrix, Point, Rect, Color, Rgb, Cmyk, Winding, FormXObject};
+use pdf::content::{Op, Matrix, Point, Rect, Color, Rgb, Cmyk, Winding, FormXObject, TextMode};
 use pdf::error::{PdfError, Result};
 use pdf::content::TextDrawAdjusted;
-use crate::backend::{Backend, BlendMode};
+use crate::backend::{Backend, BlendMode, Sampling};
+use crate::options::{RenderContext, DrawKind};
+use crate::tile::draw_bounds;
+use crate::image::device_size;
 
 use pathfinder_geometry::{
     vector::Vector2F,
@@ -81,10 +84,22 @@ pub struct RenderState<'a, R: Resolve, B: Backend> {
     resolve: &'a R,
     resources: &'a Resources,
     backend: &'a mut B,
+    context: &'a RenderContext<'a>,
+    marked_content: Vec<MarkedContent>,
+    // number of entries in `marked_content` inherited from the enclosing content stream
+    marked_content_base: usize,
+}
+
+/// an open marked content sequence
+#[derive(Copy, Clone)]
+struct MarkedContent {
+    // false for content in a hidden optional content group
+    visible: bool,
+    mcid: Option<i32>,
 }
 
 impl<'a, R: Resolve, B: Backend> RenderState<'a, R, B> {
-    pub fn new(backend: &'a mut B, resolve: &'a R, resources: &'a Resources, root_transformation: Transform2F) -> Self {
+    pub fn new(backend: &'a mut B, resolve: &'a R, resources: &'a Resources, root_transformation: Transform2F, context: &'a RenderContext<'a>) -> Self {
         let graphics_state = GraphicsState {
             transform: root_transformation,
             fill_color: Fill::black(),
@@ -97,6 +112,7 @@ impl<'a, R: Resolve, B: Backend> RenderState<'a, R, B> {
             stroke_alpha: 1.0,
             clip_path: None,
             clip_path_id: None,
+            clip_rect: None,
             fill_color_space: &ColorSpace::DeviceRGB,
             stroke_color_space: &ColorSpace::DeviceRGB,
             stroke_style: StrokeStyle {
@@ -123,21 +139,102 @@ impl<'a, R: Resolve, B: Backend> RenderState<'a, R, B> {
             resources,
             resolve,
             backend,
+            context,
+            marked_content: vec![],
+            marked_content_base: 0,
         }
     }
+    pub fn backend(&mut self) -> &mut B {
+        self.backend
+    }
+    /// Intersect the clip with the device space bounds of `rect`, given in the current user space.
+    ///
+    /// Clip paths of the content drawn afterwards stay within it.
+    pub fn clip_rect(&mut self, rect: RectF) {
+        self.graphics_state.intersect_clip_rect(self.graphics_state.transform * rect);
+        self.backend.set_clip_path(self.graphics_state.clip_path.as_ref().map(|c| &c.outline));
+    }
+    /// concatenate `transform` to the current transformation matrix
+    pub fn concat_transform(&mut self, transform: Transform2F) {
+        self.graphics_state.transform = self.graphics_state.transform * transform;
+    }
+    /// draw `outline`, given in the current user space, like a path of the content stream
+    pub fn draw_outline(&mut self, outline: Outline, mode: &DrawMode, fill_rule: FillRule) {
+        self.current_contour.clear();
+        self.current_outline = outline;
+        self.draw(mode, fill_rule);
+    }
     fn draw(&mut self, mode: &DrawMode, fill_rule: FillRule) {
         self.flush();
-        self.backend.draw(&self.current_outline, mode, fill_rule, self.graphics_state.transform);
+        if !self.hidden() && self.context.options.vectors {
+            let bounds = draw_bounds(self.current_outline.bounds(), mode, self.graphics_state.transform);
+            if self.context.visible(DrawKind::Path, bounds, self.graphics_state.clip_bounds()) {
+                let points: usize = self.current_outline.contours().iter().map(|c| c.len() as usize).sum();
+                self.context.add_memory(points * std::mem::size_of::<Vector2F>());
+                self.backend.draw(&self.current_outline, mode, fill_rule, self.graphics_state.transform);
+            }
+        }
         self.current_outline.clear();
     }
-    #[allow(unused_variables)]
+    /// true inside marked content of a hidden optional content group
+    fn hidden(&self) -> bool {
+        self.marked_content.iter().any(|mc| !mc.visible)
+    }
+    /// MCID of the innermost marked content sequence that has one
+    fn mcid(&self) -> Option<i32> {
+        self.marked_content.iter().rev().find_map(|mc| mc.mcid)
+    }
+    fn is_oc_visible(&self, oc: Option<&Primitive>) -> bool {
+        let (config, oc) = match (self.context.options.optional_content, oc) {
+            (Some(config), Some(oc)) => (config, oc),
+            _ => return true
+        };
+        match *oc {
+            Primitive::Name(ref name) => match self.resources.properties.get(name.as_str()) {
+                Some(properties) => config.is_properties_visible(properties, self.resolve),
+                None => {
+                    warn!("optional content {} not in resources", name);
+                    true
+                }
+            },
+            ref p => config.is_visible(p, self.resolve)
+        }
+    }
     pub fn draw_op(&mut self, op: &'a Op, op_nr: usize) -> Result<()> {
+        self.context.count_op()?;
         self.backend.inspect_op(op);
         self.backend.bug_op(op_nr);
+        match self.op(op, op_nr) {
+            Err(e) if self.context.skip_failed(op_nr, op, &e) => {
+                self.backend.bug_op_failed(op_nr, op, &e);
+                Ok(())
+            }
+            r => r
+        }
+    }
+    #[allow(unused_variables)]
+    fn op(&mut self, op: &'a Op, op_nr: usize) -> Result<()> {
         match *op {
-            Op::BeginMarkedContent { .. } => {}
-            Op::EndMarkedContent { .. } => {}
-            Op::MarkedContentPoint { .. } => {}
+            Op::BeginMarkedContent { ref tag, ref properties } => {
+                let visible = match tag.as_str() {
+                    "OC" => self.is_oc_visible(properties.as_ref()),
+                    _ => true
+                };
+                let dict = self.marked_content_properties(properties.as_ref());
+                let mcid = dict.and_then(|d| d.get("MCID")).and_then(|p| p.as_integer().ok());
+                self.backend.begin_marked_content(tag, dict);
+                self.marked_content.push(MarkedContent { visible, mcid });
+            }
+            Op::EndMarkedContent { .. } => {
+                if self.marked_content.len() > self.marked_content_base {
+                    self.marked_content.pop();
+                    self.backend.end_marked_content();
+                }
+            }
+            Op::MarkedContentPoint { ref tag, ref properties } => {
+                let dict = self.marked_content_properties(properties.as_ref());
+                self.backend.marked_content_point(tag, dict);
+            }
             Op::Close => {
                 self.current_contour.close();
             }
@@ -288,16 +385,18 @@ impl<'a, R: Resolve, B: Backend> RenderState<'a, R, B> {
             Op::SetTextMatrix { matrix } => self.text_state.set_matrix(matrix.cvt()),
             Op::TextNewline => self.text_state.next_line(),
             Op::TextDraw { ref text } => {
+                let context = self.context;
                 self.text(|backend, text_state, graphics_state, span| {
-                    text_state.draw_text(backend, graphics_state, &text.data, span);
+                    text_state.draw_text(backend, graphics_state, context, &text.data, span);
                 });
             },
             Op::TextDrawAdjusted { ref array } => {
+                let context = self.context;
                 self.text(|backend, text_state, graphics_state, span| {
                     for arg in array {
                         match *arg {
                             TextDrawAdjusted::Text(ref data) => {
-                                text_state.draw_text(backend, graphics_state, data.as_bytes(), span);
+                                text_state.draw_text(backend, graphics_state, context, data.as_bytes(), span);
                             },
                             TextDrawAdjusted::Spacing(offset) => {
                                 // because why not PDF…
@@ -308,16 +407,24 @@ impl<'a, R: Resolve, B: Backend> RenderState<'a, R, B> {
                     }
                 });
             },
+            Op::XObject { .. } | Op::InlineImage { .. } if self.hidden() => {}
             Op::XObject { ref name } => {
                 let &xobject_ref = self.resources.xobjects.get(name).ok_or(PdfError::NotFound { word: name.as_str().into()})?;
                 let xobject = self.resolve.get(xobject_ref)?;
                 let mode = self.blend_mode();
                 match *xobject {
                     XObject::Image(ref im) => {
-                        self.backend.draw_image(xobject_ref, im, self.resources, self.graphics_state.transform, mode, self.resolve);
+                        if self.context.options.images && self.is_oc_visible(im.other.get("OC")) && self.image_visible() {
+                            // at full size, as not every backend decodes at a reduced size
+                            self.context.add_memory(im.width as usize * im.height as usize * 4);
+                            self.backend.set_image_sampling(self.image_sampling(im));
+                            self.backend.draw_image(xobject_ref, im, self.resources, self.graphics_state.transform, mode, self.resolve);
+                        }
                     }
                     XObject::Form(ref content) => {
-                        self.draw_form(content)?;
+                        if self.is_oc_visible(content.dict().other.get("OC")) {
+                            self.draw_form(content)?;
+                        }
                     }
                     XObject::Postscript(ref ps) => {
                         let data = ps.data(self.resolve)?;
@@ -326,8 +433,12 @@ impl<'a, R: Resolve, B: Backend> RenderState<'a, R, B> {
                     }
                 }
             },
+            Op::InlineImage { .. } if !self.context.options.images => {}
+            Op::InlineImage { .. } if !self.image_visible() => {}
             Op::InlineImage { ref image } => {
                 let mode = self.blend_mode();
+                self.context.add_memory(image.width as usize * image.height as usize * 4);
+                self.backend.set_image_sampling(self.image_sampling(image));
                 self.backend.draw_inline_image(image, &self.resources, self.graphics_state.transform, mode, self.resolve);
             }
         }
@@ -343,12 +454,40 @@ impl<'a, R: Resolve, B: Backend> RenderState<'a, R, B> {
         }
     }
 
+    /// whether an image drawn now (in the unit square) would be visible
+    fn image_visible(&self) -> bool {
+        let bounds = self.graphics_state.transform * RectF::new(Vector2F::zero(), Vector2F::splat(1.0));
+        self.context.visible(DrawKind::Image, bounds, self.graphics_state.clip_bounds())
+    }
+    /// The sampling forced by the options, or smooth if `im` asks for `/Interpolate` or is drawn smaller than its size.
+    fn image_sampling(&self, im: &ImageXObject) -> Sampling {
+        if let Some(sampling) = self.context.options.image_sampling {
+            return sampling;
+        }
+        let (width, height) = device_size(self.graphics_state.transform);
+        if im.interpolate || (width <= im.width as u32 && height <= im.height as u32) {
+            Sampling::Smooth
+        } else {
+            Sampling::Nearest
+        }
+    }
     fn text(&mut self, inner: impl FnOnce(&mut B, &mut TextState, &mut GraphicsState, &mut Span)) {
         let mut span = Span::default();
         let tm = self.text_state.text_matrix;
         let origin = tm.translation();
 
-        inner(&mut self.backend, &mut self.text_state, &mut self.graphics_state, &mut span);
+        let hidden = self.hidden();
+        if hidden || !self.context.options.text {
+            // still advance the text position
+            let mode = std::mem::replace(&mut self.text_state.mode, TextMode::Invisible);
+            inner(&mut self.backend, &mut self.text_state, &mut self.graphics_state, &mut span);
+            self.text_state.mode = mode;
+        } else {
+            inner(&mut self.backend, &mut self.text_state, &mut self.graphics_state, &mut span);
+        }
+        if hidden {
+            return;
+        }
 
         let transform = self.graphics_state.transform * tm * Transform2F::from_scale(Vector2F::new(1.0, -1.0));
         let p1 = origin;
@@ -367,6 +506,7 @@ impl<'a, R: Resolve, B: Backend> RenderState<'a, R, B> {
             alpha: self.graphics_state.fill_color_alpha,
             mode: self.text_state.mode,
             transform,
+            mcid: self.mcid(),
         });
     }
 
@@ -410,21 +550,40 @@ impl<'a, R: Resolve, B: Backend> RenderState<'a, R, B> {
             current_contour: Contour::new(),
             backend: self.backend,
             resolve: self.resolve,
+            context: self.context,
+            marked_content: self.marked_content.clone(),
+            marked_content_base: self.marked_content.len(),
         };
         
         let ops = t!(form.operations(self.resolve));
-        for (i, op) in ops.iter().enumerate() {
+        let result = ops.iter().enumerate().try_for_each(|(i, op)| {
             debug!(" form op {}: {:?}", i, op);
-            inner.draw_op(op, i)?;
+            inner.draw_op(op, i)
+        });
+        inner.close_marked_content();
+        result
+    }
+    /// End the marked content sequences this content stream left open.
+    pub(crate) fn close_marked_content(&mut self) {
+        while self.marked_content.len() > self.marked_content_base {
+            self.marked_content.pop();
+            self.backend.end_marked_content();
+        }
+    }
+    fn marked_content_properties(&self, p: Option<&'a Primitive>) -> Option<&'a Dictionary> {
+        match self.get_properties(p?) {
+            Ok(dict) => Some(dict),
+            Err(e) => {
+                warn!("invalid marked content properties: {:?}", e);
+                None
+            }
         }
-
-        Ok(())
     }
-    #[allow(dead_code)]
-    fn get_properties<'b>(&'b self, p: &'b Primitive) -> Result<&'b Dictionary> {
+    fn get_properties(&self, p: &'a Primitive) -> Result<&'a Dictionary> {
+        let resources = self.resources;
         match p {
             Primitive::Dictionary(ref dict) => Ok(dict),
-            Primitive::Name(ref name) => self.resources.properties.get(name.as_str())
+            Primitive::Name(ref name) => resources.properties.get(name.as_str())
                 .map(|rc| &**rc)
                 .ok_or_else(|| {
                     PdfError::MissingEntry { typ: "Properties", field: name.into() }
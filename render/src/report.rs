@@ -0,0 +1,188 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use serde_json::{json, Value};
+use crate::fontentry::{FontEntry, TextEncoding};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodingKind {
+    /// character codes are mapped through the ToUnicode map or used as glyph ids
+    Cid,
+    /// character codes are mapped to glyphs by the font encoding
+    Cmap,
+}
+
+/// What is known about one font used in a document.
+#[derive(Debug, Clone)]
+pub struct FontInfo {
+    pub name: String,
+    /// `Type1`, `TrueType`, `Type0` …
+    pub subtype: String,
+    pub embedded: bool,
+    /// the name has a subset tag like `ABCDEF+`
+    pub subset: bool,
+    pub encoding: EncodingKind,
+    pub to_unicode: bool,
+    /// the font used instead if it is not embedded
+    pub substitute: Option<String>,
+    /// glyphs drawn with the character code as glyph id
+    pub fallback_glyphs: usize,
+}
+impl FontInfo {
+    pub fn new(entry: &FontEntry) -> Self {
+        FontInfo {
+            name: entry.name.clone(),
+            subtype: format!("{:?}", entry.pdf_font.subtype),
+            embedded: entry.substitution.is_none(),
+            subset: is_subset(&entry.name),
+            encoding: match entry.encoding {
+                TextEncoding::CID(_) => EncodingKind::Cid,
+                TextEncoding::Cmap(_) => EncodingKind::Cmap,
+            },
+            to_unicode: entry.has_to_unicode,
+            substitute: entry.substitute.clone(),
+            fallback_glyphs: entry.fallback_glyphs(),
+        }
+    }
+    pub fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "subtype": self.subtype,
+            "embedded": self.embedded,
+            "subset": self.subset,
+            "encoding": match self.encoding {
+                EncodingKind::Cid => "CID",
+                EncodingKind::Cmap => "Cmap",
+            },
+            "to_unicode": self.to_unicode,
+            "substitute": self.substitute,
+            "fallback_glyphs": self.fallback_glyphs,
+        })
+    }
+}
+
+/// What a cache remembers of a loaded font for the report, without keeping the font alive.
+pub(crate) struct LoadedFont {
+    info: FontInfo,
+    fallback_glyphs: Arc<AtomicUsize>,
+}
+impl LoadedFont {
+    pub fn new(entry: &FontEntry) -> Self {
+        LoadedFont {
+            info: FontInfo::new(entry),
+            fallback_glyphs: entry.fallback_glyphs.clone(),
+        }
+    }
+    /// If `entry` is this font loaded again, count its glyphs here as well.
+    pub fn adopt(&self, entry: &mut FontEntry) -> bool {
+        if entry.name != self.info.name {
+            return false;
+        }
+        entry.fallback_glyphs = self.fallback_glyphs.clone();
+        true
+    }
+    pub fn info(&self) -> FontInfo {
+        FontInfo {
+            fallback_glyphs: self.fallback_glyphs.load(Ordering::Relaxed),
+            ..self.info.clone()
+        }
+    }
+}
+
+/// The fonts a cache has loaded so far, in load order.
+///
+/// Glyph counts cover everything rendered with the cache, so use one cache per document.
+#[derive(Debug, Clone, Default)]
+pub struct FontReport {
+    pub fonts: Vec<FontInfo>,
+    /// fonts that were not embedded and had no substitute
+    pub missing: Vec<String>,
+}
+impl FontReport {
+    pub(crate) fn new<'a>(loaded: impl Iterator<Item=&'a LoadedFont>, missing: impl Iterator<Item=String>) -> Self {
+        FontReport {
+            fonts: loaded.map(LoadedFont::info).collect(),
+            missing: missing.collect(),
+        }
+    }
+    pub fn to_json(&self) -> Value {
+        json!({
+            "fonts": self.fonts.iter().map(FontInfo::to_json).collect::<Vec<_>>(),
+            "missing": self.missing,
+        })
+    }
+}
+
+fn is_subset(name: &str) -> bool {
+    match name.split_once('+') {
+        Some((tag, _)) => tag.len() == 6 && tag.chars().all(|c| c.is_ascii_uppercase()),
+        None => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pathfinder_geometry::transform2d::Transform2F;
+    use crate::{Cache, CacheLimits, render_page};
+    use crate::tracer::Tracer;
+
+    #[test]
+    fn subset_names() {
+        assert!(is_subset("ABCDEF+Arial"));
+        assert!(!is_subset("Arial"));
+        assert!(!is_subset("ABCDE+Arial"));
+        assert!(!is_subset("abcdef+Arial"));
+        assert!(!is_subset("ABC1EF+Arial"));
+    }
+
+    #[test]
+    fn report_json() {
+        let info = FontInfo {
+            name: "ABCDEF+Arial".into(),
+            subtype: "TrueType".into(),
+            embedded: true,
+            subset: true,
+            encoding: EncodingKind::Cmap,
+            to_unicode: false,
+            substitute: None,
+            fallback_glyphs: 2,
+        };
+        let report = FontReport { fonts: vec![info], missing: vec!["Foo".into()] };
+        assert_eq!(report.to_json(), json!({
+            "fonts": [{
+                "name": "ABCDEF+Arial",
+                "subtype": "TrueType",
+                "embedded": true,
+                "subset": true,
+                "encoding": "Cmap",
+                "to_unicode": false,
+                "substitute": null,
+                "fallback_glyphs": 2,
+            }],
+            "missing": ["Foo"],
+        }));
+    }
+
+    #[test]
+    fn loaded_fonts() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/compressed.tracemonkey-pldi-09.pdf");
+        let file = pdf::file::FileOptions::cached().open(path).unwrap();
+        let resolver = file.resolver();
+        let page = file.get_page(0).unwrap();
+        // nothing stays cached, so every font is loaded again each time it is used
+        let cache = Cache::builder().limits(CacheLimits { fonts: 0, ..CacheLimits::default() }).build().unwrap();
+        for _ in 0 .. 2 {
+            let mut tracer = Tracer::new(&cache);
+            render_page(&mut tracer, &resolver, &page, Transform2F::default()).unwrap();
+        }
+        let report = cache.font_report();
+        let mut names: Vec<_> = report.fonts.iter().map(|f| f.name.as_str()).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert!(!names.is_empty());
+        assert_eq!(names.len(), report.fonts.len(), "{:?}", report.fonts);
+        assert!(report.fonts.iter().all(|f| f.embedded && f.substitute.is_none()));
+        assert!(report.fonts.iter().any(|f| f.subset));
+        assert!(report.missing.is_empty());
+    }
+}
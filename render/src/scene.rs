@@ -1,9 +1,9 @@
-use pathfinder_color::{ColorF, ColorU};
+use pathfinder_color::ColorF;
 use pathfinder_content::{
     fill::FillRule,
     stroke::{OutlineStrokeToFill},
     outline::Outline,
-    pattern::{Pattern},
+    pattern::{Pattern, Image},
     dash::OutlineDash,
 };
 use pathfinder_renderer::{
@@ -11,13 +11,13 @@ use pathfinder_renderer::{
     paint::{PaintId, Paint},
 };
 use pathfinder_geometry::{
-    vector::{Vector2F},
+    vector::{Vector2F, Vector2I},
     rect::RectF, transform2d::Transform2F,
 };
 use pdf::object::{Ref, XObject, ImageXObject, Resolve, Resources, MaybeRef};
 use crate::backend;
 
-use super::{FontEntry, TextSpan, DrawMode, Backend, Fill, Cache};
+use super::{FontEntry, TextSpan, DrawMode, Backend, Fill, Cache, load_image_scaled, device_size};
 use pdf::font::Font as PdfFont;
 use pdf::error::PdfError;
 use std::sync::Arc;
@@ -26,15 +26,17 @@ pub struct SceneBackend<'a> {
     clip_path: Option<ClipPath>,
     clip_path_id: Option<ClipPathId>,
     scene: &'a mut Scene,
-    cache: Cache,
+    cache: &'a Cache,
+    sampling: backend::Sampling,
 }
 impl<'a> SceneBackend<'a> {
-    pub fn new(cache: Cache, scene:  &'a mut Scene) -> Self {
+    pub fn new(cache: &'a Cache, scene:  &'a mut Scene) -> Self {
         SceneBackend { 
             clip_path: None, 
             clip_path_id: None, 
             scene: scene, 
             cache: cache,
+            sampling: backend::Sampling::Smooth,
         }
     }
     fn clip_path_id(&mut self) -> Option<ClipPathId> {
@@ -57,6 +59,22 @@ impl<'a> SceneBackend<'a> {
         };
         self.scene.push_paint(&paint)
     }
+    /// draw `image` into the unit square transformed by `transform`
+    fn draw_image_pattern(&mut self, image: &Image, transform: Transform2F) {
+        let size_f = image.size().to_f32();
+        let outline = Outline::from_rect(transform * RectF::new(Vector2F::default(), Vector2F::new(1.0, 1.0)));
+        let im_tr = transform
+            * Transform2F::from_scale(Vector2F::new(1.0 / size_f.x(), -1.0 / size_f.y()))
+            * Transform2F::from_translation(Vector2F::new(0.0, -size_f.y()));
+        let mut pattern = Pattern::from_image(image.clone());
+        pattern.apply_transform(im_tr);
+        pattern.set_smoothing_enabled(self.sampling == backend::Sampling::Smooth);
+        let paint = Paint::from_pattern(pattern);
+        let paint_id = self.scene.push_paint(&paint);
+        let mut draw_path = DrawPath::new(outline, paint_id);
+        draw_path.set_clip_path(self.clip_path_id());
+        self.scene.push_draw_path(draw_path);
+    }
 }
 impl<'a> Backend for SceneBackend<'a> {
     fn set_clip_path(&mut self, _path: Option<&Outline>) {
@@ -64,10 +82,6 @@ impl<'a> Backend for SceneBackend<'a> {
     }
     fn set_view_box(&mut self, view_box: RectF) {
         self.scene.set_view_box(view_box);
-
-        let white = self.scene.push_paint(&Paint::from_color(ColorU::white()));
-        self.scene.push_draw_path(DrawPath::new(Outline::from_rect(view_box), white));
-
     }
     fn draw(&mut self, outline: &Outline, mode: &DrawMode, fill_rule: FillRule, transform: Transform2F) {
         match *mode {
@@ -104,25 +118,23 @@ impl<'a> Backend for SceneBackend<'a> {
             _ => {}
         }
     }
-    fn draw_image(&mut self, xobject_ref: Ref<XObject>, im: &ImageXObject, resources: &Resources, transform: Transform2F, mode: backend::BlendMode, resolve: &impl Resolve) {
-        if let Ok(ref image) = *self.cache.get_image(xobject_ref, im, resources, resolve).0 {
-            let size = image.size();
-            let size_f = size.to_f32();
-            let outline = Outline::from_rect(transform * RectF::new(Vector2F::default(), Vector2F::new(1.0, 1.0)));
-            let im_tr = transform
-                * Transform2F::from_scale(Vector2F::new(1.0 / size_f.x(), -1.0 / size_f.y()))
-                * Transform2F::from_translation(Vector2F::new(0.0, -size_f.y()));
-            let mut pattern = Pattern::from_image(image.clone());
-            pattern.apply_transform(im_tr);
-            let paint = Paint::from_pattern(pattern);
-            let paint_id = self.scene.push_paint(&paint);
-            let mut draw_path = DrawPath::new(outline, paint_id);
-            draw_path.set_clip_path(self.clip_path_id());
-            self.scene.push_draw_path(draw_path);
+    fn draw_image(&mut self, xobject_ref: Ref<XObject>, im: &ImageXObject, resources: &Resources, transform: Transform2F, _mode: backend::BlendMode, resolve: &impl Resolve) {
+        if let Ok(ref image) = *self.cache.get_image_scaled(xobject_ref, im, resources, resolve, device_size(transform)).0 {
+            self.draw_image_pattern(image, transform);
         }
     }
-    fn draw_inline_image(&mut self, _im: &Arc<ImageXObject>, _resources: &Resources, _transform: Transform2F, mode: backend::BlendMode, _resolve: &impl Resolve) {
-
+    fn draw_inline_image(&mut self, im: &Arc<ImageXObject>, resources: &Resources, transform: Transform2F, _mode: backend::BlendMode, resolve: &impl Resolve) {
+        // inline images have no reference to cache them by
+        match load_image_scaled(im, resources, resolve, device_size(transform)) {
+            Ok(image) => {
+                let size = Vector2I::new(image.width() as i32, image.height() as i32);
+                self.draw_image_pattern(&Image::new(size, Arc::new(image.into_data().into())), transform);
+            }
+            Err(e) => warn!("failed to load inline image: {:?}", e),
+        }
+    }
+    fn set_image_sampling(&mut self, sampling: backend::Sampling) {
+        self.sampling = sampling;
     }
 
     fn get_font(&mut self, font_ref: &MaybeRef<PdfFont>, resolve: &impl Resolve) -> Result<Option<Arc<FontEntry>>, PdfError> {
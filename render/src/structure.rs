@@ -0,0 +1,557 @@
+//! Logical structure (tagged PDF), PDF 32000-1:2008, 14.7 and 14.8,
+//! and text export in reading order.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use pdf::object::{Resolve, PlainRef, Trailer};
+use pdf::primitive::{Primitive, Dictionary};
+use pdf::error::Result;
+use crate::TextSpan;
+
+/// The structure tree of a tagged PDF.
+#[derive(Debug)]
+pub struct StructTree {
+    pub children: Vec<StructNode>,
+}
+
+#[derive(Debug)]
+pub enum StructNode {
+    Element(StructElem),
+    /// marked content sequence on a page
+    Content { page: Option<PlainRef>, mcid: i32 },
+}
+
+#[derive(Debug)]
+pub struct StructElem {
+    /// structure type after applying the role map, like `P`, `H1` or `Table`
+    pub kind: String,
+    pub page: Option<PlainRef>,
+    pub alt: Option<String>,
+    pub actual_text: Option<String>,
+    /// `/ListNumbering` attribute of a list, like `Decimal` or `Disc`
+    pub list_numbering: Option<String>,
+    pub children: Vec<StructNode>,
+}
+
+impl StructTree {
+    /// Read the structure tree from the `/StructTreeRoot` of the catalog,
+    /// like `StructTree::load(&file.trailer, &resolver)`.
+    ///
+    /// Returns `None` if the document is not tagged.
+    pub fn load(trailer: &Trailer, resolve: &impl Resolve) -> Result<Option<StructTree>> {
+        let catalog = resolve.resolve(trailer.root.get_ref().get_inner())?.into_dictionary()?;
+        let root = match catalog.get("StructTreeRoot") {
+            Some(p) => p.clone().resolve(resolve)?.into_dictionary()?,
+            None => return Ok(None)
+        };
+        let mut role_map = HashMap::new();
+        if let Some(map) = root.get("RoleMap") {
+            for (key, value) in map.clone().resolve(resolve)?.into_dictionary()?.iter() {
+                if let Primitive::Name(ref name) = *value {
+                    role_map.insert(key.as_str().to_owned(), name.as_str().to_owned());
+                }
+            }
+        }
+        let mut parser = Parser { resolve, role_map, visited: HashSet::new() };
+        let children = match root.get("K") {
+            Some(k) => parser.kids(k, None),
+            None => vec![]
+        };
+        Ok(Some(StructTree { children }))
+    }
+}
+
+struct Parser<'a, R: Resolve> {
+    resolve: &'a R,
+    role_map: HashMap<String, String>,
+    visited: HashSet<PlainRef>,
+}
+impl<'a, R: Resolve> Parser<'a, R> {
+    fn kids(&mut self, k: &Primitive, page: Option<PlainRef>) -> Vec<StructNode> {
+        match *k {
+            Primitive::Array(ref arr) => arr.iter().flat_map(|k| self.kid(k, page)).collect(),
+            ref k => self.kid(k, page).into_iter().collect()
+        }
+    }
+    fn kid(&mut self, k: &Primitive, page: Option<PlainRef>) -> Option<StructNode> {
+        let dict = match *k {
+            Primitive::Integer(mcid) => return Some(StructNode::Content { page, mcid }),
+            Primitive::Reference(r) => {
+                if !self.visited.insert(r) {
+                    warn!("cycle in structure tree at {:?}", r);
+                    return None;
+                }
+                match self.resolve.resolve(r) {
+                    Ok(Primitive::Dictionary(dict)) => dict,
+                    Ok(p) => return self.kid(&p, page),
+                    Err(e) => {
+                        warn!("can't resolve structure element {:?}: {:?}", r, e);
+                        return None;
+                    }
+                }
+            }
+            Primitive::Dictionary(ref dict) => dict.clone(),
+            _ => return None
+        };
+        let page = match dict.get("Pg") {
+            Some(&Primitive::Reference(r)) => Some(r),
+            _ => page
+        };
+        match dict.get("Type") {
+            Some(Primitive::Name(ref t)) if t.as_str() == "MCR" => {
+                if dict.get("Stm").is_some() {
+                    // content in a form xobject is not tracked by page
+                    return None;
+                }
+                let mcid = dict.get("MCID")?.as_integer().ok()?;
+                return Some(StructNode::Content { page, mcid });
+            }
+            Some(Primitive::Name(ref t)) if t.as_str() == "OBJR" => return None,
+            _ => {}
+        }
+        let kind = match dict.get("S") {
+            Some(Primitive::Name(ref s)) => self.role(s.as_str()),
+            _ => String::from("NonStruct")
+        };
+        let children = match dict.get("K") {
+            Some(k) => self.kids(k, page),
+            None => vec![]
+        };
+        Some(StructNode::Element(StructElem {
+            kind,
+            page,
+            alt: text_entry(&dict, "Alt", self.resolve),
+            actual_text: text_entry(&dict, "ActualText", self.resolve),
+            list_numbering: list_numbering(&dict, self.resolve),
+            children,
+        }))
+    }
+    fn role(&self, kind: &str) -> String {
+        let mut kind = kind;
+        for _ in 0 .. 16 {
+            match self.role_map.get(kind) {
+                Some(mapped) if mapped != kind => kind = mapped,
+                _ => break
+            }
+        }
+        kind.to_owned()
+    }
+}
+
+fn text_entry(dict: &Dictionary, key: &str, resolve: &impl Resolve) -> Option<String> {
+    let p = dict.get(key)?.clone().resolve(resolve).ok()?;
+    Some(p.as_string().ok()?.to_string_lossy())
+}
+
+/// `/ListNumbering` from the attribute objects (`/A`), which may be a single dictionary or an array
+/// of dictionaries and revision numbers.
+fn list_numbering(dict: &Dictionary, resolve: &impl Resolve) -> Option<String> {
+    let attributes = match dict.get("A")?.clone().resolve(resolve).ok()? {
+        Primitive::Array(arr) => arr,
+        p => vec![p],
+    };
+    attributes.into_iter().find_map(|a| {
+        match a.resolve(resolve).ok()?.into_dictionary().ok()?.get("ListNumbering") {
+            Some(Primitive::Name(ref name)) => Some(name.as_str().to_owned()),
+            _ => None
+        }
+    })
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+}
+
+/// Text of marked content sequences, collected from the `TextSpan`s passed to `Backend::add_text`.
+#[derive(Default)]
+pub struct TaggedText {
+    content: HashMap<(PlainRef, i32), String>,
+}
+impl TaggedText {
+    pub fn new() -> Self {
+        TaggedText::default()
+    }
+
+    /// Add the text spans of one page, in content stream order.
+    /// `page` is the reference of the page, `page.get_ref().get_inner()` of a `PageRc`.
+    pub fn add_page<'s>(&mut self, page: PlainRef, spans: impl IntoIterator<Item=&'s TextSpan>) {
+        let mut last: HashMap<i32, &TextSpan> = HashMap::new();
+        for span in spans {
+            let mcid = match span.mcid {
+                Some(mcid) => mcid,
+                None => continue
+            };
+            let text = self.content.entry((page, mcid)).or_default();
+            if let Some(prev) = last.get(&mcid) {
+                // a gap of more than a quarter em or a different line
+                let gap = span.rect.min_x() - prev.rect.max_x();
+                let new_line = (span.rect.max_y() - prev.rect.max_y()).abs() > 0.5 * prev.rect.height();
+                if (new_line || gap > 0.25 * span.rect.height()) && !text.ends_with(' ') && !span.text.starts_with(' ') {
+                    text.push(' ');
+                }
+            }
+            text.push_str(&span.text);
+            last.insert(mcid, span);
+        }
+    }
+
+    /// Write the text in logical structure order.
+    pub fn export(&self, tree: &StructTree, format: ExportFormat) -> String {
+        let mut out = String::new();
+        for node in tree.children.iter() {
+            self.block(node, format, &mut out);
+        }
+        out.truncate(out.trim_end().len());
+        out.push('\n');
+        out
+    }
+
+    fn inline(&self, node: &StructNode, out: &mut String) {
+        match *node {
+            StructNode::Content { page: Some(page), mcid } => {
+                if let Some(text) = self.content.get(&(page, mcid)) {
+                    if !out.is_empty() && !out.ends_with(' ') && !text.starts_with(' ') {
+                        out.push(' ');
+                    }
+                    out.push_str(text.trim_end());
+                }
+            }
+            StructNode::Content { page: None, .. } => {}
+            StructNode::Element(ref elem) => {
+                if let Some(ref text) = elem.actual_text {
+                    out.push_str(text);
+                    return;
+                }
+                for child in elem.children.iter() {
+                    self.inline(child, out);
+                }
+            }
+        }
+    }
+    fn text(&self, node: &StructNode) -> String {
+        let mut s = String::new();
+        self.inline(node, &mut s);
+        s.trim().to_owned()
+    }
+
+    fn block(&self, node: &StructNode, format: ExportFormat, out: &mut String) {
+        let elem = match *node {
+            StructNode::Element(ref elem) => elem,
+            ref content => {
+                let text = self.text(content);
+                if !text.is_empty() {
+                    paragraph(out, format, "p", &text);
+                }
+                return;
+            }
+        };
+        let kind = elem.kind.as_str();
+        match kind {
+            "H" | "H1" | "H2" | "H3" | "H4" | "H5" | "H6" => {
+                let level = kind[1..].parse::<usize>().unwrap_or(1);
+                let text = self.text(node);
+                match format {
+                    ExportFormat::Markdown => { let _ = write!(out, "{} {}\n\n", "#".repeat(level), text); }
+                    ExportFormat::Html => { let _ = write!(out, "<h{0}>{1}</h{0}>\n", level, escape(&text)); }
+                }
+            }
+            "P" | "Caption" | "Note" | "TOCI" => {
+                let text = self.text(node);
+                if !text.is_empty() {
+                    paragraph(out, format, "p", &text);
+                }
+            }
+            "BlockQuote" => {
+                let text = self.text(node);
+                paragraph(out, format, "blockquote", &text);
+            }
+            "Code" => {
+                let text = self.text(node);
+                match format {
+                    ExportFormat::Markdown => { let _ = write!(out, "```\n{}\n```\n\n", text); }
+                    ExportFormat::Html => { let _ = write!(out, "<pre><code>{}</code></pre>\n", escape(&text)); }
+                }
+            }
+            "Figure" | "Formula" => {
+                let alt = elem.alt.clone().unwrap_or_default();
+                match format {
+                    ExportFormat::Markdown => { let _ = write!(out, "![{}]()\n\n", alt); }
+                    ExportFormat::Html => { let _ = write!(out, "<figure>{}</figure>\n", escape(&alt)); }
+                }
+            }
+            "L" => self.list(elem, format, out),
+            "Table" => self.table(elem, format, out),
+            "Artifact" => {}
+            // grouping elements and anything unknown
+            _ => {
+                if elem.children.iter().all(|c| is_inline(c)) {
+                    let text = self.text(node);
+                    if !text.is_empty() {
+                        paragraph(out, format, "p", &text);
+                    }
+                } else {
+                    for child in elem.children.iter() {
+                        self.block(child, format, out);
+                    }
+                }
+            }
+        }
+    }
+
+    fn list(&self, list: &StructElem, format: ExportFormat, out: &mut String) {
+        // label, text and nested lists of each item
+        let mut items: Vec<(String, String, String)> = vec![];
+        for item in list.children.iter() {
+            let item = match *item {
+                StructNode::Element(ref e) if e.kind == "LI" => e,
+                ref other => {
+                    let text = self.text(other);
+                    if !text.is_empty() {
+                        items.push((String::new(), text, String::new()));
+                    }
+                    continue;
+                }
+            };
+            let mut label = String::new();
+            let mut text = String::new();
+            let mut nested = String::new();
+            for part in item.children.iter() {
+                match *part {
+                    StructNode::Element(ref e) if e.kind == "Lbl" => label = self.text(part),
+                    StructNode::Element(ref e) if e.kind == "L" => self.list(e, format, &mut nested),
+                    _ => {
+                        let part = self.text(part);
+                        if !text.is_empty() && !part.is_empty() {
+                            text.push(' ');
+                        }
+                        text.push_str(&part);
+                    }
+                }
+            }
+            items.push((label, text, nested));
+        }
+        let ordered = match list.list_numbering.as_deref() {
+            Some(numbering) => !matches!(numbering, "None" | "Disc" | "Circle" | "Square"),
+            None => items.iter().any(|(label, _, _)| is_number(label)),
+        };
+        match format {
+            ExportFormat::Markdown => {
+                for (i, (label, text, nested)) in items.iter().enumerate() {
+                    let marker = match ordered {
+                        true if is_number(label) => label.clone(),
+                        true => format!("{}.", i + 1),
+                        false => "-".into(),
+                    };
+                    let _ = writeln!(out, "{} {}", marker, text);
+                    for line in nested.trim_end().lines() {
+                        let _ = writeln!(out, "  {}", line);
+                    }
+                }
+                out.push('\n');
+            }
+            ExportFormat::Html => {
+                let tag = if ordered { "ol" } else { "ul" };
+                let _ = writeln!(out, "<{}>", tag);
+                for (label, text, nested) in items.iter() {
+                    out.push_str("<li>");
+                    if !label.is_empty() {
+                        let _ = write!(out, "<span class=\"lbl\">{}</span> ", escape(label));
+                    }
+                    out.push_str(&escape(text));
+                    if !nested.is_empty() {
+                        out.push('\n');
+                        out.push_str(nested);
+                    }
+                    out.push_str("</li>\n");
+                }
+                let _ = writeln!(out, "</{}>", tag);
+            }
+        }
+    }
+
+    fn table(&self, table: &StructElem, format: ExportFormat, out: &mut String) {
+        // rows may be grouped in THead, TBody and TFoot
+        let mut rows: Vec<Vec<(bool, String)>> = vec![];
+        let mut stack: Vec<&StructNode> = table.children.iter().rev().collect();
+        while let Some(node) = stack.pop() {
+            let elem = match *node {
+                StructNode::Element(ref e) => e,
+                _ => continue
+            };
+            match elem.kind.as_str() {
+                "TR" => rows.push(elem.children.iter().filter_map(|cell| match *cell {
+                    StructNode::Element(ref c) => Some((c.kind == "TH", self.text(cell))),
+                    _ => None
+                }).collect()),
+                _ => stack.extend(elem.children.iter().rev()),
+            }
+        }
+        if rows.is_empty() {
+            return;
+        }
+        match format {
+            ExportFormat::Markdown => {
+                let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+                for (i, row) in rows.iter().enumerate() {
+                    out.push('|');
+                    for c in 0 .. columns {
+                        let cell = row.get(c).map(|(_, s)| s.replace('|', "\\|")).unwrap_or_default();
+                        let _ = write!(out, " {} |", cell);
+                    }
+                    out.push('\n');
+                    if i == 0 {
+                        out.push('|');
+                        out.push_str(&" --- |".repeat(columns));
+                        out.push('\n');
+                    }
+                }
+                out.push('\n');
+            }
+            ExportFormat::Html => {
+                out.push_str("<table>\n");
+                for row in rows.iter() {
+                    out.push_str("<tr>");
+                    for (header, cell) in row.iter() {
+                        let tag = if *header { "th" } else { "td" };
+                        let _ = write!(out, "<{0}>{1}</{0}>", tag, escape(cell));
+                    }
+                    out.push_str("</tr>\n");
+                }
+                out.push_str("</table>\n");
+            }
+        }
+    }
+}
+
+/// a list label like `3.` or `3)`
+fn is_number(label: &str) -> bool {
+    label.trim_end_matches(|c| c == '.' || c == ')').parse::<u32>().is_ok()
+}
+
+fn is_inline(node: &StructNode) -> bool {
+    match *node {
+        StructNode::Content { .. } => true,
+        StructNode::Element(ref e) => matches!(e.kind.as_str(),
+            "Span" | "Quote" | "Note" | "Reference" | "BibEntry" | "Link" | "Annot" | "Ruby" | "Warichu"
+            | "RB" | "RT" | "RP" | "WT" | "WP" | "Lbl"
+        ),
+    }
+}
+
+fn paragraph(out: &mut String, format: ExportFormat, tag: &str, text: &str) {
+    match (format, tag) {
+        (ExportFormat::Markdown, "blockquote") => { let _ = write!(out, "> {}\n\n", text); }
+        (ExportFormat::Markdown, _) => { let _ = write!(out, "{}\n\n", text); }
+        (ExportFormat::Html, _) => { let _ = write!(out, "<{0}>{1}</{0}>\n", tag, escape(text)); }
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pathfinder_geometry::{transform2d::Transform2F, rect::RectF, vector::Vector2F};
+    use pdf::content::TextMode;
+    use crate::{Cache, Fill, render_page};
+    use crate::tracer::{Tracer, DrawItem};
+    use crate::writer::{PdfBuilder, load_page};
+
+    /// A tagged document whose content order differs from the logical order.
+    fn tagged_document(b: &mut PdfBuilder) {
+        let p = b.object("<< /S /P /Pg 3 0 R /K 1 >>");
+        let h = b.object("<< /S /Title /Pg 3 0 R /K 0 >>");
+        let lbl = b.object("<< /S /Lbl /Pg 3 0 R /K 3 >>");
+        let body = b.object("<< /S /LBody /Pg 3 0 R /K 2 >>");
+        let li = b.object(&format!("<< /S /LI /K [{} 0 R {} 0 R] >>", lbl, body));
+        let l = b.object(&format!("<< /S /L /A << /O /List /ListNumbering /Decimal >> /K {} 0 R >>", li));
+        let bullet = b.object("<< /S /Lbl /Pg 3 0 R /K 5 >>");
+        let point = b.object("<< /S /LBody /Pg 3 0 R /K 4 >>");
+        let li2 = b.object(&format!("<< /S /LI /K [{} 0 R {} 0 R] >>", bullet, point));
+        let l2 = b.object(&format!("<< /S /L /A [<< /O /Layout >> 0 << /O /List /ListNumbering /Disc >>] /K {} 0 R >>", li2));
+        let doc = b.object(&format!("<< /S /Document /K [{} 0 R {} 0 R {} 0 R {} 0 R] >>", h, p, l, l2));
+        b.catalog_entry(&format!("/MarkInfo << /Marked true >> /StructTreeRoot << /Type /StructTreeRoot /K {} 0 R /RoleMap << /Title /H1 >> >>", doc));
+    }
+
+    #[test]
+    fn tagged_text_export() {
+        let mut b = PdfBuilder::new();
+        tagged_document(&mut b);
+        let (file, page) = load_page(b.finish(b""));
+        let resolver = file.resolver();
+        let tree = StructTree::load(&file.trailer, &resolver).unwrap().unwrap();
+
+        // the spans a backend would get for the text, see `tagged_text`
+        let span = |mcid: i32, text: &str| TextSpan {
+            rect: RectF::new(Vector2F::new(10. * mcid as f32, 0.), Vector2F::new(10., 10.)),
+            width: 10.,
+            bbox: None,
+            font_size: 10.,
+            font: None,
+            text: text.into(),
+            chars: vec![],
+            color: Fill::black(),
+            alpha: 1.,
+            transform: Transform2F::default(),
+            mode: TextMode::Fill,
+            mcid: Some(mcid),
+        };
+        let spans = [span(1, "Body"), span(2, "Item"), span(3, "1."), span(0, "Heading"), span(4, "Point"), span(5, "*")];
+        let mut text = TaggedText::new();
+        text.add_page(page.get_ref().get_inner(), &spans);
+        assert_eq!(text.export(&tree, ExportFormat::Markdown), "# Heading\n\nBody\n\n1. Item\n\n- Point\n");
+        assert_eq!(text.export(&tree, ExportFormat::Html), concat!(
+            "<h1>Heading</h1>\n<p>Body</p>\n",
+            "<ol>\n<li><span class=\"lbl\">1.</span> Item</li>\n</ol>\n",
+            "<ul>\n<li><span class=\"lbl\">*</span> Point</li>\n</ul>\n",
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs STANDARD_FONTS"]
+    fn tagged_text() {
+        assert!(std::env::var_os("STANDARD_FONTS").is_some(), "tagged_text needs the standard fonts in STANDARD_FONTS");
+        let mut b = PdfBuilder::new();
+        b.resource("/Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >> >>");
+        tagged_document(&mut b);
+        let data = b.finish(b"
+            BT /F1 10 Tf
+            /P << /MCID 1 >> BDC 10 60 Td (Body) Tj EMC
+            /LBody << /MCID 2 >> BDC 0 -20 Td (Item) Tj EMC
+            /Lbl << /MCID 3 >> BDC -8 0 Td (1.) Tj EMC
+            /H1 << /MCID 0 >> BDC 8 40 Td (Heading) Tj EMC
+            /LBody << /MCID 4 >> BDC 0 -60 Td (Point) Tj EMC
+            /Lbl << /MCID 5 >> BDC -8 0 Td (*) Tj EMC
+            ET
+        ");
+
+        let (file, page) = load_page(data);
+        let resolver = file.resolver();
+        let cache = Cache::new().unwrap();
+        let mut tracer = Tracer::new(&cache);
+        render_page(&mut tracer, &resolver, &page, Transform2F::default()).unwrap();
+        let spans: Vec<_> = tracer.finish().into_iter().filter_map(|item| match item {
+            DrawItem::Text(span) => Some(span),
+            _ => None
+        }).collect();
+
+        let tree = StructTree::load(&file.trailer, &resolver).unwrap().unwrap();
+        let mut text = TaggedText::new();
+        text.add_page(page.get_ref().get_inner(), &spans);
+        assert_eq!(text.export(&tree, ExportFormat::Markdown), "# Heading\n\nBody\n\n1. Item\n\n- Point\n");
+    }
+}
@@ -3,13 +3,17 @@ use pathfinder_geometry::{
     transform2d::Transform2F,
 };
 use font::GlyphId;
+use pathfinder_content::stroke::{StrokeStyle, LineCap, LineJoin};
 use super::{
     BBox,
     fontentry::{FontEntry, TextEncoding},
     graphicsstate::{GraphicsState},
     DrawMode,
+    backend::Stroke,
     Backend,
     TextChar,
+    options::{RenderContext, DrawKind},
+    tile::draw_bounds,
 };
 use std::convert::TryInto;
 use pdf::content::TextMode;
@@ -64,7 +68,7 @@ impl TextState {
         self.text_matrix = m;
         self.line_matrix = m;
     }
-    pub fn draw_text(&mut self, backend: &mut impl Backend, gs: &GraphicsState, data: &[u8], span: &mut Span) {
+    pub fn draw_text(&mut self, backend: &mut impl Backend, gs: &GraphicsState, context: &RenderContext, data: &[u8], span: &mut Span) {
         let e = match self.font_entry {
             Some(ref e) => e,
             None => {
@@ -114,10 +118,24 @@ impl TextState {
         };
         let e = self.font_entry.as_ref().expect("no font");
 
+        let font_matrix = e.font.font_matrix();
         let tr = Transform2F::row_major(
             self.horiz_scale * self.font_size, 0., 0.,
             0., self.font_size, self.rise
-        ) * e.font.font_matrix();
+        ) * font_matrix;
+
+        // synthetic bold: stroke the outline with the fill color
+        let draw_mode = match (draw_mode, e.substitution) {
+            (Some(DrawMode::Fill(fill, alpha)), Some(sub)) if sub.embolden > 0.0 => Some(DrawMode::FillStroke(fill, alpha, fill, alpha, Stroke {
+                dash_pattern: None,
+                style: StrokeStyle {
+                    line_width: sub.embolden / font_matrix.m11(),
+                    line_cap: LineCap::Round,
+                    line_join: LineJoin::Round,
+                },
+            })),
+            (mode, _) => mode
+        };
         
         for (cid, gid, unicode) in glyphs {
             let is_space = matches!(e.encoding, TextEncoding::Cmap(_)) && unicode.as_deref() == Some(" ");
@@ -127,10 +145,11 @@ impl TextState {
                 Some(gid) => gid,
                 None => {
                     debug!("no glyph for cid {}", cid);
+                    e.count_fallback_glyph();
                     GlyphId(cid as _)
                 } // lets hope that works…
             };
-            let glyph = e.font.glyph(gid);
+            let glyph = e.glyph(gid);
             let width: f32 = e.widths.as_ref().map(|w| w.get(cid as usize) * 0.001 * self.horiz_scale * self.font_size)
                 .or_else(|| glyph.as_ref().map(|g| tr.m11() * g.metrics.advance))
                 .unwrap_or(0.0);
@@ -150,11 +169,24 @@ impl TextState {
                 continue;
             }
             if let Some(glyph) = glyph {
-                let transform = gs.transform * self.text_matrix * tr;
+                let mut transform = gs.transform * self.text_matrix * tr;
+                if let Some(sub) = e.substitution {
+                    // match the advance from /Widths and slant for synthetic italic
+                    let pdf_width = e.widths.as_ref().map(|w| w.get(cid as usize) * 0.001);
+                    let advance = glyph.metrics.advance * font_matrix.m11();
+                    let scale = match pdf_width {
+                        Some(w) if sub.scale_to_widths && w > 0.0 && advance > 0.0 => (w / advance).max(0.5).min(2.0),
+                        _ => 1.0
+                    };
+                    transform = transform * Transform2F::row_major(scale, sub.shear, 0., 0., 1., 0.);
+                }
                 if glyph.path.len() != 0 {
                     span.bbox.add(gs.transform * transform * glyph.path.bounds());
                     if let Some(ref draw_mode) = draw_mode {
-                        backend.draw_glyph(&glyph, draw_mode, transform);
+                        let bounds = draw_bounds(glyph.path.bounds(), draw_mode, transform);
+                        if context.visible(DrawKind::Glyph, bounds, gs.clip_bounds()) {
+                            backend.draw_glyph(&glyph, draw_mode, transform);
+                        }
                     }
                 }
             } else {
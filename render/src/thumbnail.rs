@@ -0,0 +1,78 @@
+use image::{ImageBuffer, Rgba, imageops::{resize, FilterType}};
+use pathfinder_color::ColorU;
+use pathfinder_geometry::transform2d::Transform2F;
+use pdf::object::{Object, Page, Resolve, ImageXObject};
+use crate::{Cache, RasterBackend, ImageData, RenderOptions, RenderError, Sampling, PageBox, page_bounds, load_image_scaled, render_page_with};
+
+// operators drawn for a thumbnail, the rest of a very complex page is left out
+const MAX_OPS: usize = 20_000;
+
+/// A thumbnail of `page` that fits into `max_size` × `max_size` pixels.
+///
+/// The embedded `/Thumb` image is used if the page has one. It is scaled down if it is too large, but never up.
+/// Otherwise the page is rendered at that size with simplified settings: without annotations,
+/// with images decoded at the thumbnail size and sampled with nearest neighbour, skipping operators that fail,
+/// and only the first 20000 operators.
+pub fn thumbnail(cache: &Cache, resolve: &impl Resolve, page: &Page, max_size: u32) -> Result<ImageData<'static>, RenderError> {
+    let max_size = max_size.max(1);
+    if let Some(image) = embedded_thumbnail(resolve, page, max_size) {
+        return Ok(fit(image, max_size));
+    }
+
+    let size = page_bounds(page, PageBox::default())?.size();
+    let scale = max_size as f32 / size.x().max(size.y());
+    let options = RenderOptions {
+        annotations: false,
+        image_sampling: Some(Sampling::Nearest),
+        max_ops: Some(MAX_OPS),
+        lenient: true,
+        ..RenderOptions::default()
+    };
+    let mut backend = RasterBackend::new(Some(cache));
+    match render_page_with(&mut backend, resolve, page, Transform2F::from_scale(scale), &options) {
+        Ok(_) => {}
+        Err(RenderError::BudgetExceeded(budget)) => debug!("thumbnail cut off at {:?}", budget),
+        Err(e) => return Err(e)
+    }
+    Ok(backend.finish())
+}
+
+/// the decoded `/Thumb` image of `page`, if it has a valid one
+fn embedded_thumbnail(resolve: &impl Resolve, page: &Page, max_size: u32) -> Option<ImageData<'static>> {
+    let thumb = page.other.get("Thumb")?;
+    let image = match ImageXObject::from_primitive(thumb.clone(), resolve) {
+        Ok(image) => image,
+        Err(e) => {
+            warn!("invalid thumbnail: {}", e);
+            return None;
+        }
+    };
+    let resources = page.resources().ok()?;
+    match load_image_scaled(&image, resources, resolve, (max_size, max_size)) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            warn!("can't load thumbnail: {}", e);
+            None
+        }
+    }
+}
+
+/// scale `image` down to fit into `max_size`
+fn fit(image: ImageData<'static>, max_size: u32) -> ImageData<'static> {
+    let (width, height) = (image.width(), image.height());
+    if width <= max_size && height <= max_size {
+        return image;
+    }
+    let scale = max_size as f32 / width.max(height) as f32;
+    let (w, h) = (((width as f32 * scale) as u32).max(1), ((height as f32 * scale) as u32).max(1));
+    let src: ImageBuffer<Rgba<u8>, &[u8]> = match ImageBuffer::from_raw(width, height, image.rgba_data()) {
+        Some(src) => src,
+        None => return image
+    };
+    let pixels: Vec<ColorU> = resize(&src, w, h, FilterType::Triangle)
+        .into_raw()
+        .chunks_exact(4)
+        .map(|c| ColorU::new(c[0], c[1], c[2], c[3]))
+        .collect();
+    ImageData::new(pixels, w, h).unwrap_or(image)
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+use pathfinder_geometry::{
+    rect::RectF,
+    transform2d::Transform2F,
+    vector::Vector2F,
+};
+use pathfinder_color::ColorU;
+use pathfinder_content::{
+    fill::FillRule,
+    outline::Outline,
+    stroke::LineJoin,
+};
+use pdf::object::{Page, Ref, XObject, ImageXObject, Resolve, Resources, MaybeRef};
+use pdf::primitive::{Name, Dictionary};
+use pdf::content::Op;
+use pdf::font::Font as PdfFont;
+use pdf::error::PdfError;
+use font::Glyph;
+use crate::{Backend, DrawMode, BlendMode, Sampling, FontEntry, TextSpan, Cache, RasterBackend, ImageData, RenderOptions, RenderError, Rendered, render_page_with};
+use crate::lru::{LruCache, CacheStats};
+
+/// Device space bounds of `bounds` drawn with `mode` and `transform`, including the stroke width.
+pub(crate) fn draw_bounds(bounds: RectF, mode: &DrawMode, transform: Transform2F) -> RectF {
+    let stroke = match *mode {
+        DrawMode::Fill(..) => return transform * bounds,
+        DrawMode::Stroke(_, _, ref stroke) | DrawMode::FillStroke(_, _, _, _, ref stroke) => stroke,
+    };
+    // miters can extend up to the miter limit times the half line width
+    let join = match stroke.style.line_join {
+        LineJoin::Miter(limit) => limit.max(1.0),
+        _ => 1.0
+    };
+    let d = 0.5 * stroke.style.line_width * join;
+    transform * bounds.dilate(Vector2F::splat(d))
+}
+
+/// Forwards to a backend that only covers `tile`, and drops draw calls that are completely outside of it.
+///
+/// The view box of the inner backend is the tile, moved to the origin.
+pub struct TileBackend<'b, B: Backend> {
+    inner: &'b mut B,
+    tile: RectF,
+    culled: usize,
+}
+impl<'b, B: Backend> TileBackend<'b, B> {
+    /// `tile` in device space, relative to the top left corner of the page.
+    pub fn new(inner: &'b mut B, tile: RectF) -> Self {
+        TileBackend { inner, tile, culled: 0 }
+    }
+    /// number of draw calls that were dropped
+    pub fn culled(&self) -> usize {
+        self.culled
+    }
+    fn visible(&mut self, bounds: RectF) -> bool {
+        let visible = bounds.intersects(self.tile);
+        if !visible {
+            self.culled += 1;
+        }
+        visible
+    }
+    fn offset(&self) -> Transform2F {
+        Transform2F::from_translation(-self.tile.origin())
+    }
+}
+impl<'b, B: Backend> Backend for TileBackend<'b, B> {
+    fn set_clip_path(&mut self, path: Option<&Outline>) {
+        let offset = self.offset();
+        match path {
+            Some(path) => self.inner.set_clip_path(Some(&path.clone().transformed(&offset))),
+            None => self.inner.set_clip_path(None)
+        }
+    }
+    fn draw(&mut self, outline: &Outline, mode: &DrawMode, fill_rule: FillRule, transform: Transform2F) {
+        if self.visible(draw_bounds(outline.bounds(), mode, transform)) {
+            self.inner.draw(outline, mode, fill_rule, self.offset() * transform);
+        }
+    }
+    fn set_view_box(&mut self, _r: RectF) {
+        self.inner.set_view_box(RectF::new(Vector2F::zero(), self.tile.size()));
+    }
+    fn fill_background(&mut self, r: RectF, color: ColorU) {
+        self.inner.fill_background(self.offset() * r, color);
+    }
+    fn draw_image(&mut self, xref: Ref<XObject>, im: &ImageXObject, resources: &Resources, transform: Transform2F, mode: BlendMode, resolve: &impl Resolve) {
+        if self.visible(transform * RectF::new(Vector2F::zero(), Vector2F::splat(1.0))) {
+            self.inner.draw_image(xref, im, resources, self.offset() * transform, mode, resolve);
+        }
+    }
+    fn draw_inline_image(&mut self, im: &Arc<ImageXObject>, resources: &Resources, transform: Transform2F, mode: BlendMode, resolve: &impl Resolve) {
+        if self.visible(transform * RectF::new(Vector2F::zero(), Vector2F::splat(1.0))) {
+            self.inner.draw_inline_image(im, resources, self.offset() * transform, mode, resolve);
+        }
+    }
+    fn set_image_sampling(&mut self, sampling: Sampling) {
+        self.inner.set_image_sampling(sampling);
+    }
+    fn draw_glyph(&mut self, glyph: &Glyph, mode: &DrawMode, transform: Transform2F) {
+        if self.visible(draw_bounds(glyph.path.bounds(), mode, transform)) {
+            self.inner.draw_glyph(glyph, mode, self.offset() * transform);
+        }
+    }
+    fn get_font(&mut self, font_ref: &MaybeRef<PdfFont>, resolve: &impl Resolve) -> Result<Option<Arc<FontEntry>>, PdfError> {
+        self.inner.get_font(font_ref, resolve)
+    }
+    fn add_text(&mut self, span: TextSpan) {
+        self.inner.add_text(span);
+    }
+    fn begin_marked_content(&mut self, tag: &Name, properties: Option<&Dictionary>) {
+        self.inner.begin_marked_content(tag, properties);
+    }
+    fn end_marked_content(&mut self) {
+        self.inner.end_marked_content();
+    }
+    fn marked_content_point(&mut self, tag: &Name, properties: Option<&Dictionary>) {
+        self.inner.marked_content_point(tag, properties);
+    }
+    fn bug_text_no_font(&mut self, data: &[u8]) {
+        self.inner.bug_text_no_font(data);
+    }
+    fn bug_text_invisible(&mut self, text: &str) {
+        self.inner.bug_text_invisible(text);
+    }
+    fn bug_postscript(&mut self, data: &[u8]) {
+        self.inner.bug_postscript(data);
+    }
+    fn bug_op(&mut self, op_nr: usize) {
+        self.inner.bug_op(op_nr);
+    }
+    fn bug_op_failed(&mut self, op_nr: usize, op: &Op, error: &PdfError) {
+        self.inner.bug_op_failed(op_nr, op, error);
+    }
+    fn inspect_op(&mut self, op: &Op) {
+        self.inner.inspect_op(op);
+    }
+}
+
+/// Render the rectangle `tile` of the page at `scale` device pixels per millimeter.
+///
+/// `tile` is in the device space of the whole page at that scale, with the origin at the top left corner.
+/// The backend receives a view box of the size of the tile.
+pub fn render_tile(backend: &mut impl Backend, resolve: &impl Resolve, page: &Page, scale: f32, tile: RectF, options: &RenderOptions) -> Result<Rendered, RenderError> {
+    let mut backend = TileBackend::new(backend, tile);
+    let options = RenderOptions { viewport: Some(tile), ..options.clone() };
+    render_page_with(&mut backend, resolve, page, Transform2F::from_scale(scale), &options)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    /// page number
+    pub page: u32,
+    /// bits of the scale
+    pub zoom: u32,
+    pub x: u32,
+    pub y: u32,
+}
+impl TileKey {
+    /// tile `(x, y)` of page `page` at `scale` pixels per millimeter
+    pub fn new(page: u32, scale: f32, x: u32, y: u32) -> Self {
+        TileKey { page, zoom: scale.to_bits(), x, y }
+    }
+    pub fn scale(&self) -> f32 {
+        f32::from_bits(self.zoom)
+    }
+}
+
+/// Rendered square tiles of `tile_size` pixels, keyed by page, zoom level and tile index.
+pub struct TileCache {
+    tiles: LruCache<TileKey, Arc<ImageData<'static>>>,
+    tile_size: u32,
+}
+impl TileCache {
+    /// Keep at most `budget` bytes of pixels.
+    pub fn new(tile_size: u32, budget: usize) -> Self {
+        TileCache {
+            tiles: LruCache::new(budget, |im: &Arc<ImageData>| im.data().len() * 4),
+            tile_size: tile_size.max(1),
+        }
+    }
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+    /// The tile `key` of `page`, rendered into a `RasterBackend` if not cached.
+    /// Failed tiles are not cached.
+    pub fn get(&self, cache: &Cache, resolve: &impl Resolve, page: &Page, key: TileKey, options: &RenderOptions) -> Result<Arc<ImageData<'static>>, RenderError> {
+        self.tiles.try_get(key, || {
+            let size = self.tile_size as f32;
+            let tile = RectF::new(Vector2F::new(key.x as f32, key.y as f32) * size, Vector2F::splat(size));
+            let mut backend = RasterBackend::new(Some(cache));
+            render_tile(&mut backend, resolve, page, key.scale(), tile, options)?;
+            Ok(Arc::new(backend.finish()))
+        })
+    }
+    /// Drop all tiles, for example after the document changed.
+    pub fn clear(&self) {
+        self.tiles.clear();
+    }
+    pub fn stats(&self) -> CacheStats {
+        self.tiles.stats()
+    }
+}
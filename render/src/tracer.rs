@@ -11,68 +11,47 @@ use pathfinder_geometry::{
 use pathfinder_content::{
     stroke::{StrokeStyle},
 }; 
+use pathfinder_color::ColorU;
 use pdf::object::{Ref, XObject, ImageXObject, Resolve, Resources, MaybeRef};
+use pdf::primitive::{Name, Dictionary};
 use font::Glyph;
 use pdf::font::Font as PdfFont;
 use pdf::error::PdfError;
 use std::sync::Arc;
-use std::path::PathBuf;
-use crate::font::{load_font, StandardCache};
-use globalcache::sync::SyncCache;
+use crate::cache::Cache;
 use crate::backend::Stroke;
 
 pub struct Tracer<'a> {
     items: Vec<DrawItem>,
     view_box: RectF,
-    cache: &'a TraceCache,
+    cache: &'a Cache,
     op_nr: usize,
-}
-pub struct TraceCache {
-    fonts: Arc<SyncCache<usize, Option<Arc<FontEntry>>>>,
-    std: StandardCache,
-}
-impl TraceCache {
-    pub fn new() -> Self {
-        let standard_fonts = PathBuf::from(std::env::var_os("STANDARD_FONTS").expect("STANDARD_FONTS is not set. Please check https://github.com/pdf-rs/pdf_render/#fonts for instructions."));
-
-        TraceCache {
-            fonts: SyncCache::new(),
-            std: StandardCache::new(standard_fonts),
-        }
-    }
-    pub fn get_font(&self, font_ref: &MaybeRef<PdfFont>, resolve: &impl Resolve) -> Result<Option<Arc<FontEntry>>, PdfError> {
-        let mut error = None;
-        let val = self.fonts.get(&**font_ref as *const PdfFont as usize, || 
-            match load_font(font_ref, resolve, &self.std) {
-                Ok(Some(f)) => Some(Arc::new(f)),
-                Ok(None) => None,
-                Err(e) => {
-                    error = Some(e);
-                    None
-                }
-            }
-        );
-        match error {
-            None => Ok(val),
-            Some(e) => Err(e)
-        }
-    }
+    // MCID of each open marked content sequence
+    marked_content: Vec<Option<i32>>,
 }
 impl<'a> Tracer<'a> {
-    pub fn new(cache: &'a TraceCache) -> Self {
+    pub fn new(cache: &'a Cache) -> Self {
         Tracer {
             items: vec![],
             view_box: RectF::new(Vector2F::zero(), Vector2F::zero()),
             cache,
             op_nr: 0,
+            marked_content: vec![],
         }
     }
     pub fn view_box(&self) -> RectF {
         self.view_box
     }
+    /// everything traced so far
+    pub fn items(&self) -> &[DrawItem] {
+        &self.items
+    }
     pub fn finish(self) -> Vec<DrawItem> {
         self.items
     }
+    fn mcid(&self) -> Option<i32> {
+        self.marked_content.iter().rev().find_map(|&mcid| mcid)
+    }
 }
 impl<'a> Backend for Tracer<'a> {
     fn set_clip_path(&mut self, path: Option<&Outline>) {
@@ -91,17 +70,20 @@ impl<'a> Backend for Tracer<'a> {
             },
             stroke,
             transform,
+            mcid: self.mcid(),
         }));
     }
     fn set_view_box(&mut self, r: RectF) {
         self.view_box = r;
     }
+    /// the background is not part of the content
+    fn fill_background(&mut self, _r: RectF, _color: ColorU) {}
     fn draw_image(&mut self, xref: Ref<XObject>, _im: &ImageXObject, _resources: &Resources, transform: Transform2F, mode: BlendMode, _resolve: &impl Resolve) {
         let rect = transform * RectF::new(
             Vector2F::new(0.0, 0.0), Vector2F::new(1.0, 1.0)
         );
         self.items.push(DrawItem::Image(ImageObject {
-            rect, id: xref, transform, op_nr: self.op_nr, mode
+            rect, id: xref, transform, op_nr: self.op_nr, mode, mcid: self.mcid()
         }));
     }
     fn draw_inline_image(&mut self, im: &Arc<ImageXObject>, _resources: &Resources, transform: Transform2F, mode: BlendMode, _resolve: &impl Resolve) {
@@ -110,7 +92,7 @@ impl<'a> Backend for Tracer<'a> {
         );
 
         self.items.push(DrawItem::InlineImage(InlineImageObject {
-            rect, im: im.clone(), transform, op_nr: self.op_nr, mode
+            rect, im: im.clone(), transform, op_nr: self.op_nr, mode, mcid: self.mcid()
         }));
     }
     fn draw_glyph(&mut self, _glyph: &Glyph, _mode: &DrawMode, _transform: Transform2F) {}
@@ -123,6 +105,13 @@ impl<'a> Backend for Tracer<'a> {
     fn bug_op(&mut self, op_nr: usize) {
         self.op_nr = op_nr;
     }
+    fn begin_marked_content(&mut self, _tag: &Name, properties: Option<&Dictionary>) {
+        let mcid = properties.and_then(|d| d.get("MCID")).and_then(|p| p.as_integer().ok());
+        self.marked_content.push(mcid);
+    }
+    fn end_marked_content(&mut self) {
+        self.marked_content.pop();
+    }
 }
 
 #[derive(Debug)]
@@ -132,6 +121,7 @@ pub struct ImageObject {
     pub transform: Transform2F,
     pub op_nr: usize,
     pub mode: BlendMode,
+    pub mcid: Option<i32>,
 }
 #[derive(Debug)]
 pub struct InlineImageObject {
@@ -140,6 +130,7 @@ pub struct InlineImageObject {
     pub transform: Transform2F,
     pub op_nr: usize,
     pub mode: BlendMode,
+    pub mcid: Option<i32>,
 }
 
 #[derive(Debug)]
@@ -157,4 +148,5 @@ pub struct VectorPath {
     pub fill: Option<(Fill, f32)>,
     pub stroke: Option<(Fill, f32, Stroke)>,
     pub transform: Transform2F,
+    pub mcid: Option<i32>,
 }
@@ -0,0 +1,81 @@
+//! Comparison of rendered pages against the reference images in `tests/reference`.
+//!
+//! Set `PDF_RENDER_BLESS=1` to (re)write the reference images from the current output.
+//! Without it, a missing reference image fails the test.
+//! On a mismatch, the actual image and a diff image are written to `$CARGO_TARGET_TMPDIR/visual`.
+
+use image::{Rgba, RgbaImage};
+use pdf_render::ImageData;
+use std::path::PathBuf;
+
+/// YIQ distance above which two pixels are considered different (0..1)
+const THRESHOLD: f32 = 0.1;
+/// fraction of pixels allowed to differ
+const MAX_MISMATCH: f32 = 0.005;
+
+fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("reference").join(format!("{}.png", name))
+}
+fn output_dir() -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("visual");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn check(name: &str, actual: &ImageData) {
+    let reference = reference_path(name);
+    if std::env::var_os("PDF_RENDER_BLESS").is_some() {
+        actual.safe(&reference);
+        return;
+    }
+    if !reference.is_file() {
+        let out = output_dir().join(format!("{}.png", name));
+        actual.safe(&out);
+        panic!("no reference image for {}. rendered output is in {:?}, run with PDF_RENDER_BLESS=1 to accept it.", name, out);
+    }
+    let expected = image::open(&reference).unwrap().to_rgba8();
+    assert_eq!((expected.width(), expected.height()), (actual.width(), actual.height()), "{}: size differs from reference", name);
+
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    let mut mismatches = 0;
+    for (i, (e, a)) in expected.pixels().zip(actual.rgba_data().chunks_exact(4)).enumerate() {
+        let a = Rgba([a[0], a[1], a[2], a[3]]);
+        let (x, y) = (i as u32 % expected.width(), i as u32 / expected.width());
+        if distance(*e, a) > THRESHOLD * THRESHOLD {
+            mismatches += 1;
+            diff.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+        } else {
+            let g = (luma(*e) * 0.3 * 255. + 178.) as u8;
+            diff.put_pixel(x, y, Rgba([g, g, g, 255]));
+        }
+    }
+    let ratio = mismatches as f32 / (expected.width() * expected.height()).max(1) as f32;
+    if ratio > MAX_MISMATCH {
+        let dir = output_dir();
+        actual.safe(&dir.join(format!("{}.actual.png", name)));
+        diff.save(dir.join(format!("{}.diff.png", name))).unwrap();
+        panic!("{}: {} pixels ({:.2}%) differ from the reference. see {:?}", name, mismatches, ratio * 100., dir);
+    }
+}
+
+/// color on white background, channels in 0..1
+fn flatten(c: Rgba<u8>) -> [f32; 3] {
+    let a = c[3] as f32 / 255.;
+    let ch = |v: u8| (v as f32 / 255.) * a + (1.0 - a);
+    [ch(c[0]), ch(c[1]), ch(c[2])]
+}
+fn luma(c: Rgba<u8>) -> f32 {
+    let [r, g, b] = flatten(c);
+    r * 0.29889531 + g * 0.58662247 + b * 0.11448223
+}
+
+/// normalized squared YIQ distance, see "Measuring perceived color difference using YIQ NTSC transmission color space"
+fn distance(a: Rgba<u8>, b: Rgba<u8>) -> f32 {
+    let [r1, g1, b1] = flatten(a);
+    let [r2, g2, b2] = flatten(b);
+    let (dr, dg, db) = (r1 - r2, g1 - g2, b1 - b2);
+    let y = dr * 0.29889531 + dg * 0.58662247 + db * 0.11448223;
+    let i = dr * 0.59597799 - dg * 0.27417610 - db * 0.32180189;
+    let q = dr * 0.21147017 - dg * 0.52261711 + db * 0.31114694;
+    (0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / 0.5416
+}
@@ -0,0 +1,716 @@
+//! Visual regression tests.
+//!
+//! Every test builds a small synthetic PDF that exercises one group of content stream operators,
+//! renders it with the `RasterBackend` at 1px per point and compares the result
+//! against `tests/reference/<name>.png`.
+//!
+//! Text tests need the standard fonts in `STANDARD_FONTS` and are ignored by default.
+//! Run them with `cargo test -- --ignored`.
+
+mod writer;
+mod compare;
+
+use pathfinder_geometry::{transform2d::Transform2F, rect::RectF, vector::Vector2F};
+use pathfinder_color::ColorU;
+use writer::{PdfBuilder, load_page};
+use pdf_render::{render_page, render_page_with, RasterBackend, RenderOptions, OptionalContent, Fill};
+use pdf_render::{CancelToken, RenderError, Budget, PreparedPage, render_tile, TileBackend, CullCount, thumbnail, Sampling};
+use pdf_render::tracer::{Tracer, DrawItem};
+use pdf_render::Cache;
+
+fn render(data: Vec<u8>, cache: Option<&Cache>, configure: impl FnOnce(&mut RenderOptions)) -> pdf_render::ImageData<'static> {
+    let (file, page) = load_page(data);
+    let resolver = file.resolver();
+    let layers = OptionalContent::load(&file.trailer, &resolver).unwrap();
+
+    let mut backend = RasterBackend::new(cache);
+    let mut options = RenderOptions { optional_content: Some(&layers), ..RenderOptions::default() };
+    configure(&mut options);
+    render_page_with(&mut backend, &resolver, &page, Transform2F::from_scale(72. / 25.4), &options).unwrap();
+    backend.finish()
+}
+
+fn run(name: &str, build: impl FnOnce(&mut PdfBuilder) -> Vec<u8>) {
+    let mut builder = PdfBuilder::new();
+    let content = build(&mut builder);
+    let image = render(builder.finish(&content), None, |_| {});
+    compare::check(name, &image);
+}
+
+fn run_text(name: &str, build: impl FnOnce(&mut PdfBuilder) -> Vec<u8>) {
+    assert!(std::env::var_os("STANDARD_FONTS").is_some(), "{} needs the standard fonts in STANDARD_FONTS", name);
+    let cache = Cache::new().unwrap();
+    let mut builder = PdfBuilder::new();
+    builder.resource("/Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >> >>");
+    let content = build(&mut builder);
+    let image = render(builder.finish(&content), Some(&cache), |_| {});
+    compare::check(name, &image);
+}
+
+fn trace_text(font: &str, content: &[u8]) -> Vec<pdf_render::TextSpan> {
+    let cache = Cache::new().unwrap();
+    let mut builder = PdfBuilder::new();
+    builder.resource(&format!("/Font << /F1 {} >>", font));
+    let (file, page) = load_page(builder.finish(content));
+    let resolver = file.resolver();
+    let mut tracer = Tracer::new(&cache);
+    render_page(&mut tracer, &resolver, &page, Transform2F::default()).unwrap();
+    tracer.finish().into_iter().filter_map(|item| match item {
+        DrawItem::Text(span) => Some(span),
+        _ => None
+    }).collect()
+}
+
+#[test]
+fn path_fill() {
+    run("path_fill", |_| b"
+        1 0 0 rg 10 10 m 90 10 l 50 90 l h f
+        0 0 1 rg 20 60 m 20 90 50 90 50 60 c 50 30 20 30 20 60 c f
+    ".to_vec());
+}
+
+#[test]
+fn path_fill_rules() {
+    run("path_fill_rules", |_| b"
+        0 0.5 0 rg
+        10 10 35 35 re 15 15 25 25 re f
+        55 10 35 35 re 60 15 25 25 re f*
+        10 55 m 45 90 l 10 90 l 45 55 l h 55 55 35 35 re B*
+    ".to_vec());
+}
+
+#[test]
+fn path_stroke() {
+    run("path_stroke", |_| b"
+        0 0 1 RG 4 w 10 10 80 80 re S
+        1 J 2 j 5 M 0.5 i 2 w 20 20 m 80 80 l S
+        [6 3] 0 d 1 0 0 RG 20 80 m 80 20 l S
+        [] 0 d 0 1 0 rg 0 0 0 RG 1 w 35 35 30 30 re B
+        30 50 m 70 50 l h s
+        10 30 m 90 30 l n
+    ".to_vec());
+}
+
+#[test]
+fn clip_and_transform() {
+    run("clip_and_transform", |_| b"
+        q 20 20 60 60 re W n
+        1 0 0 rg 0 0 100 100 re f Q
+        q 0.7071 0.7071 -0.7071 0.7071 50 0 cm 0 0 1 rg 10 10 20 20 re f Q
+        0 1 0 rg 0 0 10 10 re f
+    ".to_vec());
+}
+
+#[test]
+fn device_colors() {
+    run("device_colors", |_| b"
+        0.5 g 0 0 50 50 re f
+        0.2 G 4 w 5 5 40 40 re S
+        1 0 0 0 k 50 0 50 50 re f
+        0 1 0 0 K 55 5 40 40 re S
+        0 0 1 rg 0 50 50 50 re f
+        /DeviceRGB cs 1 1 0 sc 50 50 50 50 re f
+        /DeviceGray CS 0 SC 55 55 40 40 re S
+    ".to_vec());
+}
+
+#[test]
+fn special_colors() {
+    run("special_colors", |b| {
+        let tint = b.object("<< /FunctionType 2 /Domain [0 1] /C0 [0 0 0 0] /C1 [0 1 1 0] /N 1 >>");
+        b.resource(&format!(
+            "/ColorSpace << /CS0 [/Indexed /DeviceRGB 1 <FF0000 0000FF>] /CS1 [/Separation /Spot /DeviceCMYK {} 0 R] >>",
+            tint
+        ));
+        b"
+            /CS0 cs 0 sc 0 0 50 100 re f
+            /CS0 cs 1 scn 10 10 30 30 re f
+            /CS1 cs 1 scn 50 0 50 100 re f
+            /CS1 CS 0.5 SCN 4 w 60 10 30 30 re S
+        ".to_vec()
+    });
+}
+
+#[test]
+fn graphics_state() {
+    run("graphics_state", |b| {
+        b.resource("/ExtGState << /GS0 << /Type /ExtGState /ca 0.5 /CA 0.5 /LW 6 >> >>");
+        b"
+            0 0 1 rg 0 0 60 60 re f
+            q /GS0 gs 1 0 0 rg 40 40 60 60 re f 0 1 0 RG 10 90 m 90 10 l S Q
+            /Perceptual ri
+            0 0 0 rg 80 0 20 20 re f
+        ".to_vec()
+    });
+}
+
+#[test]
+fn image_xobject() {
+    run("image_xobject", |b| {
+        let mut rgb = vec![];
+        for y in 0 .. 4u8 {
+            for x in 0 .. 4u8 {
+                rgb.extend_from_slice(&[x * 80, y * 80, 128]);
+            }
+        }
+        let smask = b.stream("/Type /XObject /Subtype /Image /Width 2 /Height 2 /ColorSpace /DeviceGray /BitsPerComponent 8", &[255, 128, 128, 0]);
+        let im1 = b.stream("/Type /XObject /Subtype /Image /Width 4 /Height 4 /ColorSpace /DeviceRGB /BitsPerComponent 8", &rgb);
+        let im2 = b.stream(&format!(
+            "/Type /XObject /Subtype /Image /Width 2 /Height 2 /ColorSpace /DeviceGray /BitsPerComponent 8 /SMask {} 0 R", smask
+        ), &[0, 64, 128, 255]);
+        b.resource(&format!("/XObject << /Im1 {} 0 R /Im2 {} 0 R >>", im1, im2));
+        b"
+            q 40 0 0 40 5 5 cm /Im1 Do Q
+            q 40 0 0 40 55 55 cm /Im2 Do Q
+        ".to_vec()
+    });
+}
+
+#[test]
+fn inline_image() {
+    run("inline_image", |_| {
+        let mut content = b"q 80 0 0 80 10 10 cm BI /W 2 /H 2 /CS /G /BPC 8 ID ".to_vec();
+        content.extend_from_slice(&[0, 255, 255, 0]);
+        content.extend_from_slice(b" EI Q");
+        content
+    });
+}
+
+#[test]
+fn form_xobject() {
+    run("form_xobject", |b| {
+        let form = b.stream("/Type /XObject /Subtype /Form /BBox [0 0 50 50]", b"1 0 0 rg 0 0 25 25 re f 0 0 1 rg 25 25 25 25 re f");
+        b.resource(&format!("/XObject << /Fm1 {} 0 R >>", form));
+        b"
+            /Fm1 Do
+            q 1 0 0 1 50 50 cm /Fm1 Do Q
+        ".to_vec()
+    });
+}
+
+#[test]
+fn marked_content() {
+    run("marked_content", |b| {
+        b.resource("/Properties << /MC0 << /Type /OCG /Name (Layer) >> >>");
+        b"
+            /Artifact BMC 0.5 g 0 0 50 50 re f EMC
+            /OC /MC0 BDC 1 0 0 rg 50 50 50 50 re f EMC
+            /Span << /MCID 0 >> BDC 0 0 1 rg 0 50 50 50 re f EMC
+            /Tag MP /Tag /MC0 DP
+        ".to_vec()
+    });
+}
+
+#[test]
+fn shading() {
+    run("shading", |b| {
+        b.resource("/Shading << /Sh0 << /ShadingType 2 /ColorSpace /DeviceRGB /Coords [0 0 100 0] /Function << /FunctionType 2 /Domain [0 1] /C0 [1 0 0] /C1 [0 0 1] /N 1 >> >> >>");
+        b"q 10 10 80 80 re W n /Sh0 sh Q".to_vec()
+    });
+}
+
+#[test]
+#[ignore = "needs STANDARD_FONTS"]
+fn text_show() {
+    run_text("text_show", |_| b"
+        BT /F1 18 Tf 10 70 Td (Hello) Tj
+        0 -20 Td [(W) 120 (orld)] TJ
+        20 TL T* (Line) Tj
+        ET
+    ".to_vec());
+}
+
+#[test]
+#[ignore = "needs STANDARD_FONTS"]
+fn text_state() {
+    run_text("text_state", |_| b"
+        BT /F1 12 Tf
+        1 0 0 1 5 80 Tm 2 Tc (spaced) Tj
+        1 0 0 1 5 60 Tm 0 Tc 8 Tw (word space) Tj
+        1 0 0 1 5 40 Tm 0 Tw 150 Tz (wide) Tj
+        1 0 0 1 5 20 Tm 100 Tz 4 Ts (rise) Tj
+        0 Ts 1 Tr 0.5 w 1 0 0 RG 60 0 Td (str) Tj
+        3 Tr (invisible) Tj
+        ET
+    ".to_vec());
+}
+
+#[test]
+fn annotation_appearance() {
+    run("annotation_appearance", |b| {
+        let normal = b.stream("/Type /XObject /Subtype /Form /BBox [0 0 10 10]", b"1 0 0 rg 0 0 10 10 re f");
+        let print_only = b.stream("/Type /XObject /Subtype /Form /BBox [0 0 10 10] /Matrix [0 1 -1 0 10 0]", b"0 0 1 rg 0 0 10 5 re f");
+        let a1 = b.object(&format!("<< /Type /Annot /Subtype /Square /Rect [10 10 40 40] /AP << /N {} 0 R >> >>", normal));
+        let a2 = b.object(&format!("<< /Type /Annot /Subtype /Stamp /Rect [60 10 90 40] /F 36 /AP << /N {} 0 R >> >>", print_only));
+        let a3 = b.object(&format!("<< /Type /Annot /Subtype /Square /Rect [10 60 40 90] /F 2 /AP << /N {} 0 R >> >>", normal));
+        b.page_entry(&format!("/Annots [{} 0 R {} 0 R {} 0 R]", a1, a2, a3));
+        b"0.8 g 0 0 100 100 re f".to_vec()
+    });
+}
+
+#[test]
+fn annotation_synthesized() {
+    run("annotation_synthesized", |b| {
+        let annots = [
+            "/Subtype /Highlight /Rect [10 80 60 90] /QuadPoints [10 90 60 90 10 80 60 80] /C [1 1 0]",
+            "/Subtype /Underline /Rect [10 65 60 75] /QuadPoints [10 75 60 75 10 65 60 65] /C [0 0 1]",
+            "/Subtype /StrikeOut /Rect [10 50 60 60] /QuadPoints [10 60 60 60 10 50 60 50] /C [1 0 0]",
+            "/Subtype /Square /Rect [65 65 95 95] /C [0 0 1] /IC [0.8 0.8 1] /BS << /W 2 >>",
+            "/Subtype /Circle /Rect [65 30 95 60] /C [1 0 0] /BS << /W 1 /S /D /D [2 2] >>",
+            "/Subtype /Ink /Rect [10 10 60 45] /InkList [[10 10 30 40 50 10]] /C [0 0.5 0] /Border [0 0 3]",
+            "/Subtype /Line /Rect [65 5 95 25] /L [65 5 95 25] /C [0 0 0] /CA 0.5",
+        ];
+        let refs: Vec<String> = annots.iter()
+            .map(|a| format!("{} 0 R", b.object(&format!("<< /Type /Annot {} >>", a))))
+            .collect();
+        b.page_entry(&format!("/Annots [{}]", refs.join(" ")));
+        vec![]
+    });
+}
+
+#[test]
+fn annotation_synthesized_paths() {
+    let mut b = PdfBuilder::new();
+    let annots = [
+        "/Subtype /Squiggly /Rect [10 10 60 20] /QuadPoints [10 20 60 20 10 10 60 10] /C [1 0 0]",
+        "/Subtype /FreeText /Rect [10 60 60 90] /C [1 1 0] /Border [0 0 1] /Contents (Hi) /DA (/Helv 12 Tf 0 g)",
+        // outside of the page box
+        "/Subtype /Square /Rect [150 150 170 170] /C [0 0 1]",
+    ];
+    let refs: Vec<String> = annots.iter()
+        .map(|a| format!("{} 0 R", b.object(&format!("<< /Type /Annot {} >>", a))))
+        .collect();
+    b.page_entry(&format!("/Annots [{}]", refs.join(" ")));
+    let (file, page) = load_page(b.finish(b""));
+    let resolver = file.resolver();
+    let cache = Cache::builder().build().unwrap();
+    let mut tracer = Tracer::new(&cache);
+    let rendered = render_page_with(&mut tracer, &resolver, &page, Transform2F::default(), &RenderOptions::default()).unwrap();
+    // squiggly line, FreeText background and border; the square is culled
+    assert_eq!(rendered.culling.paths, CullCount { drawn: 3, culled: 1 });
+    let paths: Vec<_> = tracer.finish().into_iter().filter_map(|item| match item {
+        DrawItem::Vector(path) => Some(path),
+        _ => None
+    }).collect();
+    assert_eq!(paths.len(), 3);
+    let zigzag = &paths[0].outline.contours()[0];
+    assert!(zigzag.len() > 2);
+    assert!(paths[0].stroke.is_some() && paths[0].fill.is_none());
+    assert_eq!(paths[1].fill, Some((Fill::Solid(1.0, 1.0, 0.0), 1.0)));
+    assert!(paths[2].stroke.is_some());
+}
+
+#[test]
+fn annotation_without_border() {
+    // the interior is filled even if there is no border to stroke
+    let mut b = PdfBuilder::new();
+    let annot = b.object("<< /Type /Annot /Subtype /Square /Rect [10 10 50 50] /C [0 0 1] /IC [1 0 0] /BS << /W 0 >> >>");
+    b.page_entry(&format!("/Annots [{} 0 R]", annot));
+    let image = render(b.finish(b""), None, |_| {});
+    assert_eq!(image.data()[70 * 100 + 30], ColorU::new(255, 0, 0, 255));
+}
+
+#[test]
+fn optional_content() {
+    run("optional_content", |b| {
+        let off = b.object("<< /Type /OCG /Name (Hidden) >>");
+        let on = b.object("<< /Type /OCG /Name (Visible) >>");
+        let all_off = b.object(&format!("<< /Type /OCMD /OCGs [{} 0 R {} 0 R] /P /AllOff >>", off, on));
+        let not_off = b.object(&format!("<< /Type /OCMD /VE [/Not {} 0 R] >>", off));
+        let form = b.stream(&format!("/Type /XObject /Subtype /Form /BBox [0 0 100 100] /OC {} 0 R", off), b"0 0 0 rg 0 0 100 100 re f");
+        b.catalog_entry(&format!("/OCProperties << /OCGs [{off} 0 R {on} 0 R] /D << /OFF [{off} 0 R] >> >>", off = off, on = on));
+        b.resource(&format!(
+            "/Properties << /L1 {} 0 R /L2 {} 0 R /M1 {} 0 R /M2 {} 0 R >> /XObject << /Fm1 {} 0 R >>",
+            off, on, all_off, not_off, form
+        ));
+        b"
+            /OC /L1 BDC 1 0 0 rg 0 0 50 50 re f EMC
+            /OC /L2 BDC 0 1 0 rg 50 0 50 50 re f EMC
+            /OC /M1 BDC 1 0 0 rg 0 50 50 50 re f EMC
+            /OC /M2 BDC 0 0 1 rg 50 50 50 50 re f EMC
+            /Fm1 Do
+        ".to_vec()
+    });
+}
+
+#[test]
+fn crop_box() {
+    // the annotation straddles the crop box and is clipped as well
+    run("crop_box", |b| {
+        let ap = b.stream("/Type /XObject /Subtype /Form /BBox [0 0 40 40]", b"0 0 1 rg 0 0 40 40 re f");
+        b.page_entry("/CropBox [10 10 90 90]");
+        b.page_entry(&format!("/Annots [<< /Type /Annot /Subtype /Square /Rect [60 60 100 100] /AP << /N {} 0 R >> >>]", ap));
+        b"1 0 0 rg 0 0 100 100 re f 0 g 0 0 20 20 re f".to_vec()
+    });
+}
+
+#[test]
+fn annotation_clip() {
+    // a clip path inside the appearance stays within the crop box
+    let mut b = PdfBuilder::new();
+    let ap = b.stream("/Type /XObject /Subtype /Form /BBox [0 0 40 40]", b"0 0 40 40 re W n 0 0 1 rg 0 0 40 40 re f");
+    b.page_entry("/CropBox [10 10 90 90]");
+    b.page_entry(&format!("/Annots [<< /Type /Annot /Subtype /Square /Rect [60 60 100 100] /AP << /N {} 0 R >> >>]", ap));
+    let (file, page) = load_page(b.finish(b""));
+    let resolver = file.resolver();
+    let cache = Cache::builder().build().unwrap();
+    let mut tracer = Tracer::new(&cache);
+    render_page(&mut tracer, &resolver, &page, Transform2F::from_scale(72. / 25.4)).unwrap();
+    let items = tracer.finish();
+    let fill = items.iter().position(|item| matches!(item, DrawItem::Vector(_))).unwrap();
+    let clip = items[.. fill].iter().rev().find_map(|item| match item {
+        DrawItem::ClipPath(clip) => Some(clip.as_ref().unwrap().bounds()),
+        _ => None
+    }).unwrap();
+    assert!((clip.width() - 30.).abs() < 1e-3 && (clip.height() - 30.).abs() < 1e-3, "{:?}", clip);
+}
+
+#[test]
+fn render_options() {
+    let mut b = PdfBuilder::new();
+    let im = b.stream("/Type /XObject /Subtype /Image /Width 1 /Height 1 /ColorSpace /DeviceGray /BitsPerComponent 8", &[0]);
+    b.resource(&format!("/XObject << /Im1 {} 0 R >>", im));
+    b.page_entry("/Annots [<< /Type /Annot /Subtype /Square /Rect [60 60 90 90] /C [0 0 1] >>]");
+    let data = b.finish(b"
+        1 0 0 rg 10 10 40 40 re f
+        q 40 0 0 40 50 10 cm /Im1 Do Q
+    ");
+    let image = render(data, None, |options| {
+        options.background = Some(ColorU::new(0, 255, 0, 128));
+        options.annotations = false;
+        options.images = false;
+    });
+    compare::check("render_options", &image);
+}
+
+#[test]
+fn lenient() {
+    let data = PdfBuilder::new().finish(b"
+        1 0 0 rg 10 10 40 40 re f
+        /Missing Do Q Q
+        0 0 1 rg 50 50 40 40 re f
+    ");
+    let (file, page) = load_page(data);
+    let resolver = file.resolver();
+    let mut backend = RasterBackend::new(None);
+    let transform = Transform2F::from_scale(72. / 25.4);
+
+    assert!(render_page_with(&mut backend, &resolver, &page, transform, &RenderOptions::default()).is_err());
+
+    let options = RenderOptions { lenient: true, ..RenderOptions::default() };
+    let rendered = render_page_with(&mut backend, &resolver, &page, transform, &options).unwrap();
+    let ops: Vec<_> = rendered.diagnostics.iter().map(|d| (d.op_nr, d.operator.as_str(), d.count)).collect();
+    assert_eq!(ops, [(3, "XObject", 1), (4, "Restore", 2)]);
+    compare::check("lenient", &backend.finish());
+}
+
+#[test]
+fn interrupted() {
+    let data = PdfBuilder::new().finish(b"
+        1 0 0 rg 10 10 40 40 re f
+        0 0 1 rg 50 50 40 40 re f
+    ");
+    let (file, page) = load_page(data);
+    let resolver = file.resolver();
+    let transform = Transform2F::from_scale(72. / 25.4);
+
+    // the second rectangle is not filled, the first one is kept
+    let mut backend = RasterBackend::new(None);
+    let options = RenderOptions { max_ops: Some(4), lenient: true, ..RenderOptions::default() };
+    match render_page_with(&mut backend, &resolver, &page, transform, &options) {
+        Err(RenderError::BudgetExceeded(Budget::Ops(4))) => {}
+        r => panic!("expected BudgetExceeded, got {:?}", r.map(|r| r.transform))
+    }
+    let image = backend.finish();
+    assert_eq!(image.data()[70 * 100 + 30], ColorU::new(255, 0, 0, 255));
+    assert_eq!(image.data()[30 * 100 + 70], ColorU::white());
+    compare::check("interrupted", &image);
+
+    // memory of the first path is counted at the next operator
+    let mut backend = RasterBackend::new(None);
+    let options = RenderOptions { max_memory: Some(1), ..RenderOptions::default() };
+    match render_page_with(&mut backend, &resolver, &page, transform, &options) {
+        Err(RenderError::BudgetExceeded(Budget::Memory(1))) => {}
+        r => panic!("expected BudgetExceeded, got {:?}", r.map(|r| r.transform))
+    }
+    let image = backend.finish();
+    assert_eq!(image.data()[70 * 100 + 30], ColorU::new(255, 0, 0, 255));
+    assert_eq!(image.data()[30 * 100 + 70], ColorU::white());
+
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    let mut backend = RasterBackend::new(None);
+    let options = RenderOptions { cancel: Some(cancel), ..RenderOptions::default() };
+    assert!(matches!(render_page_with(&mut backend, &resolver, &page, transform, &options), Err(RenderError::Cancelled)));
+}
+
+#[test]
+fn time_budget() {
+    // the clock is checked every 64 operators
+    let mut content = b"1 0 0 rg 10 10 40 40 re f\n".to_vec();
+    for _ in 0 .. 40 {
+        content.extend_from_slice(b"q Q\n");
+    }
+    content.extend_from_slice(b"0 0 1 rg 50 50 40 40 re f\n");
+    let data = PdfBuilder::new().finish(&content);
+    let (file, page) = load_page(data);
+    let resolver = file.resolver();
+
+    let mut backend = RasterBackend::new(None);
+    let options = RenderOptions { max_time: Some(std::time::Duration::ZERO), ..RenderOptions::default() };
+    match render_page_with(&mut backend, &resolver, &page, Transform2F::from_scale(72. / 25.4), &options) {
+        Err(RenderError::BudgetExceeded(Budget::Time(_))) => {}
+        r => panic!("expected BudgetExceeded, got {:?}", r.map(|r| r.transform))
+    }
+    let image = backend.finish();
+    assert_eq!(image.data()[70 * 100 + 30], ColorU::new(255, 0, 0, 255));
+    assert_eq!(image.data()[30 * 100 + 70], ColorU::white());
+}
+
+#[test]
+fn progressive() {
+    let data = PdfBuilder::new().finish(b"
+        1 0 0 rg 10 10 40 40 re f
+        0 0 1 rg 50 50 40 40 re f
+        0 1 0 RG 2 w 10 90 m 90 10 l S
+    ");
+    let (file, page) = load_page(data);
+    let resolver = file.resolver();
+    let transform = Transform2F::from_scale(72. / 25.4);
+    let options = RenderOptions::default();
+
+    let mut backend = RasterBackend::new(None);
+    render_page_with(&mut backend, &resolver, &page, transform, &options).unwrap();
+    let expected = backend.finish();
+
+    let mut prepared = PreparedPage::new(&resolver, &page, transform, &options).unwrap();
+    let num_ops = prepared.num_ops();
+    let mut backend = RasterBackend::new(None);
+    let steps = prepared.renderer(&mut backend).chunk_size(4).map(Result::unwrap).count();
+    assert_eq!(steps, (num_ops + 3) / 4);
+    assert_eq!(backend.finish().data(), expected.data());
+
+    // the page is not drawn a second time
+    let cache = Cache::builder().build().unwrap();
+    let mut tracer = Tracer::new(&cache);
+    let mut renderer = prepared.renderer(&mut tracer);
+    assert!(renderer.is_done());
+    assert!(renderer.step(100).unwrap());
+    assert!(tracer.finish().is_empty());
+
+    // the partial result holds only the first chunk
+    let mut prepared = PreparedPage::new(&resolver, &page, transform, &options).unwrap();
+    let mut tracer = Tracer::new(&cache);
+    let mut renderer = prepared.renderer(&mut tracer);
+    assert!(!renderer.step(3).unwrap());
+    let paths = |tracer: &Tracer| tracer.items().iter().filter(|item| matches!(item, DrawItem::Vector(_))).count();
+    assert_eq!(paths(renderer.backend()), 1);
+    assert!(renderer.step(100).unwrap());
+    assert_eq!(paths(renderer.backend()), 3);
+
+    // an empty content stream still takes one step for the annotations
+    let empty = PdfBuilder::new().finish(b"");
+    let (file, page) = load_page(empty);
+    let resolver = file.resolver();
+    let mut prepared = PreparedPage::new(&resolver, &page, transform, &options).unwrap();
+    let mut backend = RasterBackend::new(None);
+    assert_eq!(prepared.num_ops(), 0);
+    assert_eq!(prepared.renderer(&mut backend).count(), 1);
+}
+
+#[test]
+fn tile() {
+    let data = PdfBuilder::new().finish(b"
+        1 0 0 rg 10 10 40 40 re f
+        0 0 1 rg 50 50 40 40 re f
+        0 1 0 RG 2 w 10 90 m 90 10 l S
+    ");
+    let (file, page) = load_page(data);
+    let resolver = file.resolver();
+    // 400 × 400 pixels, the tile covers the bottom left 25 points
+    let scale = 4. * 72. / 25.4;
+    let options = RenderOptions::default();
+
+    let mut backend = RasterBackend::new(None);
+    render_page_with(&mut backend, &resolver, &page, Transform2F::from_scale(scale), &options).unwrap();
+    let full = backend.finish();
+
+    let tile = RectF::new(Vector2F::new(0., full.height() as f32 - 100.), Vector2F::new(100., 100.));
+    let mut backend = RasterBackend::new(None);
+    render_tile(&mut backend, &resolver, &page, scale, tile, &options).unwrap();
+    let image = backend.finish();
+    assert_eq!((image.width(), image.height()), (100, 100));
+    for y in 0 .. 100 {
+        let row = (full.height() - 100 + y) * full.width();
+        assert_eq!(
+            &image.data()[(y * 100) as usize .. (y * 100 + 100) as usize],
+            &full.data()[row as usize .. row as usize + 100]
+        );
+    }
+
+    // the blue square is outside of the tile
+    let mut backend = RasterBackend::new(None);
+    let mut tiled = TileBackend::new(&mut backend, tile);
+    render_page_with(&mut tiled, &resolver, &page, Transform2F::from_scale(scale), &options).unwrap();
+    assert_eq!(tiled.culled(), 1);
+}
+
+#[test]
+fn tracer_background() {
+    let data = PdfBuilder::new().finish(b"1 0 0 rg 10 10 40 40 re f");
+    let (file, page) = load_page(data);
+    let resolver = file.resolver();
+    let cache = Cache::builder().build().unwrap();
+    let mut tracer = Tracer::new(&cache);
+    render_page(&mut tracer, &resolver, &page, Transform2F::default()).unwrap();
+    let paths = tracer.finish().into_iter().filter(|item| matches!(item, DrawItem::Vector(_))).count();
+    assert_eq!(paths, 1);
+}
+
+#[test]
+fn marked_content_ids() {
+    let mut b = PdfBuilder::new();
+    // the form and the page leave their last sequence open
+    let form = b.stream("/Type /XObject /Subtype /Form /BBox [0 0 100 100]", b"/Span << /MCID 7 >> BDC 0 0 10 10 re f");
+    let ap = b.stream("/Type /XObject /Subtype /Form /BBox [0 0 10 10]", b"0 0 10 10 re f");
+    let annot = b.object(&format!("<< /Type /Annot /Subtype /Square /Rect [80 80 90 90] /AP << /N {} 0 R >> >>", ap));
+    b.resource(&format!("/XObject << /Fm1 {} 0 R >>", form));
+    b.page_entry(&format!("/Annots [{} 0 R]", annot));
+    let data = b.finish(b"
+        /P << /MCID 5 >> BDC 0 0 10 10 re f EMC
+        20 20 10 10 re f
+        /Fm1 Do
+        40 40 10 10 re f
+        /P << /MCID 6 >> BDC 60 60 10 10 re f
+    ");
+    let (file, page) = load_page(data);
+    let resolver = file.resolver();
+    let cache = Cache::builder().build().unwrap();
+    let mut tracer = Tracer::new(&cache);
+    render_page(&mut tracer, &resolver, &page, Transform2F::default()).unwrap();
+    let mcids: Vec<_> = tracer.finish().into_iter().filter_map(|item| match item {
+        DrawItem::Vector(path) => Some(path.mcid),
+        _ => None
+    }).collect();
+    assert_eq!(mcids, [Some(5), None, Some(7), None, Some(6), None]);
+}
+
+#[test]
+#[ignore = "needs STANDARD_FONTS"]
+fn substituted_font() {
+    // not embedded and not a standard name, so it is replaced by the sans fallback
+    let trace = |widths: &str, base: &str| {
+        let font = format!("<< /Type /Font /Subtype /TrueType /BaseFont /{} /FirstChar 72 /LastChar 72 /Widths [{}] >>", base, widths);
+        trace_text(&font, b"BT /F1 50 Tf 10 10 Td (H) Tj ET").remove(0)
+    };
+    let normal = trace("722", "Foo");
+    let wide = trace("1444", "Foo");
+    // the glyph is stretched to the advance from /Widths
+    assert!((normal.width - 36.1).abs() < 1e-3 && (wide.width - 72.2).abs() < 1e-3);
+    let (normal_box, wide_box) = (normal.bbox.unwrap(), wide.bbox.unwrap());
+    assert!((wide_box.width() - 2. * normal_box.width()).abs() < 0.5, "{:?} {:?}", normal_box, wide_box);
+
+    let substitution = normal.font.as_ref().unwrap().substitution.unwrap();
+    assert_eq!((substitution.embolden, substitution.shear), (0.0, 0.0));
+
+    // the style is synthesized when the substitute doesn't have it
+    let styled = trace("722", "Foo,BoldItalic");
+    let substitution = styled.font.as_ref().unwrap().substitution.unwrap();
+    assert!(substitution.embolden > 0.0 && substitution.shear > 0.0);
+    assert!(styled.bbox.unwrap().width() > normal_box.width());
+}
+
+#[test]
+fn culling() {
+    let data = PdfBuilder::new().finish(b"
+        1 0 0 rg 10 10 40 40 re f
+        0 0 1 rg 200 200 40 40 re f
+        q 0 0 20 20 re W n 0 1 0 rg 50 50 40 40 re f Q
+    ");
+    let (file, page) = load_page(data);
+    let resolver = file.resolver();
+    let mut backend = RasterBackend::new(None);
+    let rendered = render_page_with(&mut backend, &resolver, &page, Transform2F::from_scale(72. / 25.4), &RenderOptions::default()).unwrap();
+    assert_eq!(rendered.culling.paths, CullCount { drawn: 1, culled: 2 });
+}
+
+#[test]
+fn thumbnails() {
+    let data = PdfBuilder::new().finish(b"1 0 0 rg 0 0 50 100 re f");
+    let (file, page) = load_page(data);
+    let resolver = file.resolver();
+    let cache = Cache::builder().build().unwrap();
+    let image = thumbnail(&cache, &resolver, &page, 20).unwrap();
+    assert_eq!((image.width(), image.height()), (20, 20));
+    assert_eq!(image.data()[5 * 20 + 2], ColorU::new(255, 0, 0, 255));
+    assert_eq!(image.data()[5 * 20 + 17], ColorU::white());
+
+    // very long content streams are cut off
+    let mut content = b"1 0 0 rg 0 0 50 100 re f".to_vec();
+    content.extend(b" q Q".repeat(20_000));
+    content.extend(b" 0 0 1 rg 50 0 50 100 re f");
+    let data = PdfBuilder::new().finish(&content);
+    let (file, page) = load_page(data);
+    let resolver = file.resolver();
+    let image = thumbnail(&cache, &resolver, &page, 20).unwrap();
+    assert_eq!(image.data()[5 * 20 + 2], ColorU::new(255, 0, 0, 255));
+    assert_eq!(image.data()[5 * 20 + 17], ColorU::white());
+
+    let mut b = PdfBuilder::new();
+    let thumb = b.stream("/Width 2 /Height 1 /ColorSpace /DeviceRGB /BitsPerComponent 8", &[0, 0, 255, 0, 0, 255]);
+    b.page_entry(&format!("/Thumb {} 0 R", thumb));
+    let data = b.finish(b"1 0 0 rg 0 0 50 100 re f");
+    let (file, page) = load_page(data);
+    let resolver = file.resolver();
+    let image = thumbnail(&cache, &resolver, &page, 20).unwrap();
+    assert_eq!((image.width(), image.height()), (2, 1));
+    assert_eq!(image.data()[0], ColorU::new(0, 0, 255, 255));
+}
+
+#[test]
+fn image_levels() {
+    // a checkerboard drawn at 2 × 2 pixels is decoded at that size and averages to gray
+    let mut b = PdfBuilder::new();
+    let checker: Vec<u8> = (0 .. 64).map(|i| if (i + i / 8) % 2 == 0 { 0 } else { 255 }).collect();
+    let im = b.stream("/Type /XObject /Subtype /Image /Width 8 /Height 8 /ColorSpace /DeviceGray /BitsPerComponent 8", &checker);
+    b.resource(&format!("/XObject << /Im {} 0 R >>", im));
+    let data = b.finish(b"q 2 0 0 2 0 0 cm /Im Do Q");
+    let image = render(data.clone(), None, |_| {});
+    assert_eq!(image.data()[99 * 100], ColorU::new(128, 128, 128, 255));
+    assert_eq!(image.data()[98 * 100 + 1], ColorU::new(128, 128, 128, 255));
+
+    // with a cache, the reduced level is decoded once and reused
+    let cache = Cache::builder().build().unwrap();
+    for _ in 0 .. 2 {
+        let image = render(data.clone(), Some(&cache), |_| {});
+        assert_eq!(image.data()[99 * 100], ColorU::new(128, 128, 128, 255));
+    }
+    let stats = cache.stats().images;
+    assert_eq!((stats.misses, stats.hits, stats.entries), (1, 1, 1));
+    assert_eq!(stats.bytes, 2 * 2 * 4);
+}
+
+#[test]
+fn image_sampling() {
+    let build = |dict: &str| {
+        let mut b = PdfBuilder::new();
+        let im = b.stream(&format!("/Type /XObject /Subtype /Image /Width 2 /Height 1 /ColorSpace /DeviceGray /BitsPerComponent 8 {}", dict), &[0, 255]);
+        b.resource(&format!("/XObject << /Im {} 0 R >>", im));
+        b.finish(b"q 20 0 0 10 0 0 cm /Im Do Q")
+    };
+    let gray = |image: pdf_render::ImageData| image.data()[95 * 100 + 8].r;
+
+    // upscaled without /Interpolate keeps the pixels sharp
+    assert_eq!(gray(render(build(""), None, |_| {})), 0);
+    assert_eq!(gray(render(build(""), None, |o| o.image_sampling = Some(Sampling::Smooth))), 89);
+    assert_eq!(gray(render(build("/Interpolate true"), None, |_| {})), 89);
+    assert_eq!(gray(render(build("/Interpolate true"), None, |o| o.image_sampling = Some(Sampling::Nearest))), 0);
+
+    // inline images as well
+    let mut content = b"q 20 0 0 10 0 0 cm BI /W 2 /H 1 /CS /G /BPC 8 ID ".to_vec();
+    content.extend([0, 255]);
+    content.extend(b" EI Q");
+    let inline = || PdfBuilder::new().finish(&content);
+    assert_eq!(gray(render(inline(), None, |_| {})), 0);
+    assert_eq!(gray(render(inline(), None, |o| o.image_sampling = Some(Sampling::Smooth))), 89);
+}
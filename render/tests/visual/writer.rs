@@ -0,0 +1,103 @@
+//! Minimal writer for synthetic single-page PDF files.
+
+use std::fmt::Write;
+use std::sync::Arc;
+use pdf::file::{FileOptions, SyncCache, NoLog};
+use pdf::object::{PlainRef, PageRc};
+use pdf::any::AnySync;
+use pdf::PdfError;
+
+pub const PAGE_SIZE: u32 = 100;
+
+pub type PdfFile = pdf::file::File<Vec<u8>,
+    Arc<SyncCache<PlainRef, Result<AnySync, Arc<PdfError>>>>,
+    Arc<SyncCache<PlainRef, Result<Arc<[u8]>, Arc<PdfError>>>>,
+    NoLog>;
+
+/// Load a file written by `PdfBuilder::finish` and get its page.
+pub fn load_page(data: Vec<u8>) -> (PdfFile, PageRc) {
+    let file = FileOptions::cached().load(data).unwrap();
+    let page = file.get_page(0).unwrap();
+    (file, page)
+}
+
+pub struct PdfBuilder {
+    // objects 1..=4 are reserved for catalog, page tree, page and content stream
+    objects: Vec<Option<Vec<u8>>>,
+    resources: String,
+    page: String,
+    catalog: String,
+}
+impl PdfBuilder {
+    pub fn new() -> Self {
+        PdfBuilder {
+            objects: vec![None; 4],
+            resources: String::new(),
+            page: String::new(),
+            catalog: String::new(),
+        }
+    }
+    /// Add an object and return its object number.
+    pub fn object(&mut self, body: &str) -> u32 {
+        self.objects.push(Some(body.as_bytes().to_vec()));
+        self.objects.len() as u32
+    }
+    /// Add a stream object with the given dictionary entries (without `/Length`).
+    pub fn stream(&mut self, dict: &str, data: &[u8]) -> u32 {
+        self.objects.push(Some(stream(dict, data)));
+        self.objects.len() as u32
+    }
+    /// Add an entry to the page resource dictionary, like `/XObject << /Im1 5 0 R >>`.
+    pub fn resource(&mut self, entry: &str) -> &mut Self {
+        self.resources.push_str(entry);
+        self.resources.push(' ');
+        self
+    }
+    /// Add an entry to the page dictionary, like `/Annots [6 0 R]`.
+    pub fn page_entry(&mut self, entry: &str) -> &mut Self {
+        self.page.push_str(entry);
+        self.page.push(' ');
+        self
+    }
+    /// Add an entry to the document catalog, like `/OCProperties << ... >>`.
+    pub fn catalog_entry(&mut self, entry: &str) -> &mut Self {
+        self.catalog.push_str(entry);
+        self.catalog.push(' ');
+        self
+    }
+    pub fn finish(mut self, content: &[u8]) -> Vec<u8> {
+        self.objects[0] = Some(format!("<< /Type /Catalog /Pages 2 0 R {}>>", self.catalog).into_bytes());
+        self.objects[1] = Some(b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec());
+        self.objects[2] = Some(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {size} {size}] /Resources << {res}>> /Contents 4 0 R {page}>>",
+            size = PAGE_SIZE, res = self.resources, page = self.page
+        ).into_bytes());
+        self.objects[3] = Some(stream("", content));
+
+        let mut out = b"%PDF-1.7\n".to_vec();
+        let mut offsets = vec![];
+        for (i, body) in self.objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            out.extend_from_slice(body.as_ref().expect("unset object"));
+            out.extend_from_slice(b"\nendobj\n");
+        }
+        let xref_offset = out.len();
+        let mut xref = String::new();
+        writeln!(xref, "xref\n0 {}", offsets.len() + 1).unwrap();
+        xref.push_str("0000000000 65535 f \n");
+        for offset in offsets {
+            write!(xref, "{:010} 00000 n \n", offset).unwrap();
+        }
+        write!(xref, "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", self.objects.len() + 1, xref_offset).unwrap();
+        out.extend_from_slice(xref.as_bytes());
+        out
+    }
+}
+
+fn stream(dict: &str, data: &[u8]) -> Vec<u8> {
+    let mut out = format!("<< {} /Length {} >>\nstream\n", dict, data.len()).into_bytes();
+    out.extend_from_slice(data);
+    out.extend_from_slice(b"\nendstream");
+    out
+}
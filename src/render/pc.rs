@@ -94,7 +94,7 @@ pub fn render_to_window_imp(window: &Window) -> impl FnMut() -> () {
     // let mut canvas = render_on_canvas(&framebuffer_size.to_f32());
     use std::env::current_dir;
     
-    let pdf_file = read_pdf_file(current_dir().unwrap().join("compressed.tracemonkey-pldi-09.pdf"));
+    let pdf_file = read_pdf_file(current_dir().unwrap().join("render/tests/fixtures/compressed.tracemonkey-pldi-09.pdf"));
     let pdf_resolver = pdf_file.resolver();
     let mut pdf_pageRc = pdf_file.get_page(2).unwrap(); 
     let mut canvas = render_pdf_on_canvas(&
@@ -31,8 +31,8 @@ pub fn render_pdf_on_canvas(frame_size: &Vector2F,
     // let mut canvas = Canvas::new(frame_size.clone()).get_context_2d(font_context.clone());
 
     let mut secen = Scene::new();
-    let cache = pdf_render::Cache::new();
-    let mut backend = pdf_render::SceneBackend::new(cache, &mut secen);
+    let cache = pdf_render::Cache::new().unwrap();
+    let mut backend = pdf_render::SceneBackend::new(&cache, &mut secen);
 
     render_page(&mut backend,
                 &resolver,